@@ -0,0 +1,112 @@
+use rrplug::{
+    bindings::{squirrelclasstypes::*, squirreldatatypes::*},
+    high::concommands::CCommandResult,
+    prelude::*,
+};
+use std::{
+    hint::black_box,
+    sync::{atomic::Ordering, OnceLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    debug::RunState,
+    events::EventQueue,
+    exports::PLUGIN,
+    hooks::{trace_pushinteger, DetourSet, BENCH_EVENTS},
+};
+
+const DEFAULT_ITERATIONS: usize = 100_000;
+/// emptied between batches so the queue never fills up and starts dropping, which is cheaper than pushing
+const BATCH: usize = 4096;
+
+/// takes the events of the simulated vm so they never reach the real trace
+static EVENTS: OnceLock<EventQueue> = OnceLock::new();
+
+/// just enough of a vm for `sqvm_to_context` to resolve it, nothing else is ever read from it
+struct SimulatedVm {
+    sqvm: Box<HSquirrelVM>,
+    _shared_state: Box<SQSharedState>,
+    _csqvm: Box<CSquirrelVM>,
+}
+
+impl SimulatedVm {
+    fn new(context: ScriptContext) -> Self {
+        // the bindings are plain c structs so zeroed is a valid (if useless) value for them
+        let mut csqvm: Box<CSquirrelVM> = Box::new(unsafe { std::mem::zeroed() });
+        csqvm.vmContext = context as i32;
+
+        let mut shared_state: Box<SQSharedState> = Box::new(unsafe { std::mem::zeroed() });
+        shared_state.cSquirrelVM = &mut *csqvm;
+
+        let mut sqvm: Box<HSquirrelVM> = Box::new(unsafe { std::mem::zeroed() });
+        sqvm.sharedState = &mut *shared_state;
+
+        Self {
+            sqvm,
+            _shared_state: shared_state,
+            _csqvm: csqvm,
+        }
+    }
+
+    fn as_ptr(&mut self) -> *mut HSquirrelVM {
+        &mut *self.sqvm
+    }
+}
+
+/// the time per call of the `sq_pushinteger` hook without the original function
+fn measure(iterations: usize, sqvm: *mut HSquirrelVM, events: &EventQueue) -> Duration {
+    let mut elapsed = Duration::ZERO;
+    for batch in (0..iterations).step_by(BATCH) {
+        let start = Instant::now();
+        for i in batch..(batch + BATCH).min(iterations) {
+            trace_pushinteger(
+                DetourSet::Server,
+                black_box(sqvm),
+                black_box(i as SQInteger),
+            );
+        }
+        elapsed += start.elapsed();
+        events.discard();
+    }
+    elapsed / iterations.max(1) as u32
+}
+
+/// measures the debugger side of `sq_pushinteger` against a simulated server vm: dbgq_bench [iterations]
+///
+/// runs once idle with logging off and once logging, the events go into a queue of their own
+#[rrplug::concommand]
+pub fn dbgq_bench(command: CCommandResult) {
+    let iterations = command
+        .get_args()
+        .first()
+        .and_then(|iterations| iterations.parse().ok())
+        .unwrap_or(DEFAULT_ITERATIONS);
+
+    let plugin = PLUGIN.wait();
+    let state = plugin.debug_info.get(ScriptContext::SERVER);
+    if state.state() != RunState::Running {
+        log::warn!("the server vm is paused, the simulated vm would block on it");
+        return;
+    }
+
+    let mut vm = SimulatedVm::new(ScriptContext::SERVER);
+    let events = EVENTS.get_or_init(EventQueue::default);
+    // the simulated calls shouldn't show up in the hook routing
+    let served = state.hook_calls[DetourSet::Server.index()].load(Ordering::Relaxed);
+    let logging = plugin.settings.logging.swap(false, Ordering::Relaxed);
+    if plugin.is_active() {
+        log::warn!("breakpoints, break on error or the watchdog are set, the idle run isn't idle");
+    }
+
+    BENCH_EVENTS.with(|bench_events| bench_events.set(Some(events)));
+    let idle = measure(iterations, vm.as_ptr(), events);
+    plugin.settings.logging.store(true, Ordering::Relaxed);
+    let logged = measure(iterations, vm.as_ptr(), events);
+    BENCH_EVENTS.with(|bench_events| bench_events.set(None));
+
+    plugin.settings.logging.store(logging, Ordering::Relaxed);
+    state.hook_calls[DetourSet::Server.index()].store(served, Ordering::Relaxed);
+
+    log::info!("{iterations} simulated sq_pushinteger calls: {idle:?} per hook while idle and {logged:?} while logging");
+}
@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use crate::stacktrace::{CallArgs, StackTrace};
+
+/// stands in for a call whose entry was never seen, like one that was already running when the trace started
+const MISSED_CALL: &str = "<missed>";
+
+#[derive(Default)]
+pub struct CallNode {
+    pub name: String,
+    pub calls: usize,
+    pub total: Duration,
+    pub last_args: CallArgs,
+    pub children: Vec<CallNode>,
+}
+
+impl CallNode {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+}
+
+/// rebuilds the call tree of a vm from the entry/exit events of `hook_sq_call`
+///
+/// calls with the same name under the same parent are merged into one node
+#[derive(Default)]
+pub struct CallTree {
+    roots: Vec<CallNode>,
+    path: Vec<usize>,
+}
+
+impl CallTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn roots(&self) -> &[CallNode] {
+        &self.roots
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn record(&mut self, trace: &StackTrace) {
+        match trace {
+            StackTrace::Call { name, args, depth } => {
+                // the hooks know the real depth so trust them over our own path if we missed events
+                self.path.truncate(*depth);
+
+                // the calls this one is inside of were missed, stand-ins keep it at its real depth
+                while self.path.len() < *depth {
+                    if self.enter(MISSED_CALL.to_string()).is_none() {
+                        return;
+                    }
+                }
+
+                let Some(node) = self.enter(name.to_string()) else {
+                    return;
+                };
+                node.calls += 1;
+                node.last_args = *args;
+            }
+            StackTrace::Return { depth, elapsed } => {
+                self.path.truncate(depth + 1);
+
+                if self.path.len() != depth + 1 {
+                    return;
+                }
+
+                if let Some(node) = node_at(&mut self.roots, &self.path) {
+                    node.total += *elapsed;
+                }
+                self.path.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// the node below the current path with that name, added if there is none yet, becomes the new end of the path
+    fn enter(&mut self, name: String) -> Option<&mut CallNode> {
+        let siblings = children_at(&mut self.roots, &self.path)?;
+        let index = match siblings.iter().position(|node| node.name == name) {
+            Some(index) => index,
+            None => {
+                siblings.push(CallNode::new(name));
+                siblings.len() - 1
+            }
+        };
+
+        self.path.push(index);
+        siblings.get_mut(index)
+    }
+}
+
+fn children_at<'a>(roots: &'a mut Vec<CallNode>, path: &[usize]) -> Option<&'a mut Vec<CallNode>> {
+    path.iter().try_fold(roots, |children, index| {
+        children.get_mut(*index).map(|node| &mut node.children)
+    })
+}
+
+fn node_at<'a>(roots: &'a mut Vec<CallNode>, path: &[usize]) -> Option<&'a mut CallNode> {
+    let (last, parents) = path.split_last()?;
+    children_at(roots, parents)?.get_mut(*last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stacktrace::{FunctionName, InlineStr, TraceValue};
+
+    fn call(name: &str, depth: usize) -> StackTrace {
+        StackTrace::Call {
+            name: FunctionName::Script(InlineStr::new(name)),
+            args: CallArgs::default(),
+            depth,
+        }
+    }
+
+    fn ret(depth: usize, ms: u64) -> StackTrace {
+        StackTrace::Return {
+            depth,
+            elapsed: Duration::from_millis(ms),
+        }
+    }
+
+    fn names(nodes: &[CallNode]) -> Vec<&str> {
+        nodes.iter().map(|node| node.name.as_str()).collect()
+    }
+
+    #[test]
+    fn nests_calls_by_depth() {
+        let mut tree = CallTree::new();
+        for trace in [
+            call("CodeCallback_OnClientConnected", 0),
+            call("InitPlayer", 1),
+            call("GiveLoadout", 2),
+            ret(2, 1),
+            ret(1, 3),
+            call("UpdateScoreboard", 1),
+            ret(1, 2),
+            ret(0, 10),
+        ] {
+            tree.record(&trace);
+        }
+
+        let root = &tree.roots()[0];
+        assert_eq!(names(tree.roots()), ["CodeCallback_OnClientConnected"]);
+        assert_eq!(root.total, Duration::from_millis(10));
+        assert_eq!(names(&root.children), ["InitPlayer", "UpdateScoreboard"]);
+        assert_eq!(root.children[0].total, Duration::from_millis(3));
+        assert_eq!(names(&root.children[0].children), ["GiveLoadout"]);
+        assert_eq!(root.children[0].children[0].total, Duration::from_millis(1));
+    }
+
+    #[test]
+    fn merges_repeated_calls() {
+        let mut tree = CallTree::new();
+        for i in 0..3 {
+            let mut args = CallArgs::default();
+            args.push(|| TraceValue::Integer(i));
+            tree.record(&StackTrace::Call {
+                name: FunctionName::Script(InlineStr::new("OnDamage")),
+                args,
+                depth: 0,
+            });
+            tree.record(&ret(0, 2));
+        }
+
+        assert_eq!(names(tree.roots()), ["OnDamage"]);
+        let node = &tree.roots()[0];
+        assert_eq!(node.calls, 3);
+        assert_eq!(node.total, Duration::from_millis(6));
+        assert_eq!(node.last_args.to_string(), "2");
+    }
+
+    #[test]
+    fn fills_in_calls_missed_at_a_depth_gap() {
+        let mut tree = CallTree::new();
+        tree.record(&call("GiveLoadout", 2));
+        tree.record(&ret(2, 1));
+        tree.record(&call("GiveLoadout", 2));
+        tree.record(&ret(2, 1));
+        tree.record(&ret(1, 5));
+        tree.record(&ret(0, 8));
+        tree.record(&call("UpdateScoreboard", 0));
+
+        assert_eq!(names(tree.roots()), [MISSED_CALL, "UpdateScoreboard"]);
+        let missed = &tree.roots()[0];
+        assert_eq!(missed.calls, 0);
+        assert_eq!(missed.total, Duration::from_millis(8));
+        assert_eq!(names(&missed.children), [MISSED_CALL]);
+        assert_eq!(missed.children[0].total, Duration::from_millis(5));
+
+        let node = &missed.children[0].children[0];
+        assert_eq!(node.name, "GiveLoadout");
+        assert_eq!(node.calls, 2);
+        assert_eq!(node.total, Duration::from_millis(2));
+    }
+
+    #[test]
+    fn ignores_unmatched_returns() {
+        let mut tree = CallTree::new();
+        tree.record(&ret(0, 1));
+        assert!(tree.roots().is_empty());
+
+        tree.record(&call("OnThink", 0));
+        // a return from deeper than any open call
+        tree.record(&ret(3, 1));
+        tree.record(&call("UpdateHud", 1));
+        tree.record(&ret(1, 1));
+        tree.record(&ret(0, 4));
+        tree.record(&ret(0, 4));
+
+        assert_eq!(names(tree.roots()), ["OnThink"]);
+        assert_eq!(tree.roots()[0].total, Duration::from_millis(4));
+        assert_eq!(names(&tree.roots()[0].children), ["UpdateHud"]);
+    }
+}
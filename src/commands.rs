@@ -0,0 +1,385 @@
+use rrplug::{high::concommands::CCommandResult, prelude::*};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+#[cfg(feature = "bench")]
+use crate::bench::dbgq_bench;
+use crate::{
+    config::reload_config,
+    context_from_str,
+    databreak::DataPredicate,
+    debug::{PauseReason, ScriptBreakKind},
+    exports::PLUGIN,
+    session::Session,
+    ALL_CONTEXTS,
+};
+
+pub fn register_commands(engine: &EngineData, engine_token: EngineToken) {
+    macro_rules! register {
+        ($command:ident, $help:literal) => {
+            if let Err(err) =
+                engine.register_concommand(stringify!($command), $command, $help, 0, engine_token)
+            {
+                log::error!("failed to register {}: {err}", stringify!($command));
+            }
+        };
+    }
+
+    register!(dbgq_pause, "pauses a vm: dbgq_pause <vm>");
+    register!(
+        dbgq_run,
+        "lets a vm run freely until something stops it: dbgq_run <vm>"
+    );
+    register!(
+        dbgq_step,
+        "steps a paused vm to the next hook: dbgq_step <vm>"
+    );
+    register!(
+        dbgq_break_next,
+        "lets a vm run until native code calls into a script function through sq_call: dbgq_break_next <vm>"
+    );
+    register!(
+        dbgq_run_to,
+        "lets a vm run until native code calls a function through sq_call: dbgq_run_to <func> [vm]"
+    );
+    register!(dbgq_pause_all, "pauses every vm: dbgq_pause_all");
+    register!(
+        dbgq_run_all,
+        "lets every vm run freely, the server first: dbgq_run_all"
+    );
+    register!(
+        dbgq_step_all,
+        "steps every paused vm to its next hook, the server first: dbgq_step_all"
+    );
+    register!(
+        dbgq_break,
+        "breaks when a function is called: dbgq_break <func> [vm]"
+    );
+    register!(
+        dbgq_break_load,
+        "breaks before a matching script is compiled: dbgq_break_load <glob> [vm]"
+    );
+    register!(
+        dbgq_break_first_call,
+        "breaks on the first call into a matching script: dbgq_break_first_call <glob> [vm]"
+    );
+    register!(
+        dbgq_break_value,
+        "breaks when a pushed or fetched value matches: dbgq_break_value <value|=value|min..max|/regex/> [vm]"
+    );
+    register!(dbgq_clear, "removes every breakpoint: dbgq_clear [vm]");
+    register!(
+        dbgq_skip,
+        "never stops in a function or script file: dbgq_skip <func|file.nut> [vm]"
+    );
+    register!(dbgq_dump, "writes the recorded trace: dbgq_dump [file]");
+    register!(dbgq_hooks, "toggles every hook: dbgq_hooks <on|off>");
+    register!(
+        dbgq_reload_config,
+        "reads dbgquirrel.toml again: dbgq_reload_config"
+    );
+    register!(
+        dbgq_shutdown,
+        "removes every hook, releases every vm and closes the window until the game is restarted: dbgq_shutdown"
+    );
+    register!(
+        dbgq_session_export,
+        "saves breakpoints, watches and filters: dbgq_session_export <file>"
+    );
+    register!(
+        dbgq_session_import,
+        "replaces breakpoints, watches and filters: dbgq_session_import <file>"
+    );
+    #[cfg(feature = "bench")]
+    register!(
+        dbgq_bench,
+        "measures the per hook overhead on a simulated vm: dbgq_bench [iterations]"
+    );
+}
+
+/// an explicit vm or all of them if none was given
+fn contexts_from_arg(arg: Option<&String>) -> Option<Vec<ScriptContext>> {
+    match arg {
+        Some(vm) => context_from_str(vm).map(|context| vec![context]),
+        None => Some(ALL_CONTEXTS.to_vec()),
+    }
+}
+
+fn context_from_arg(command: &CCommandResult) -> Option<ScriptContext> {
+    let context = command
+        .get_args()
+        .first()
+        .and_then(|vm| context_from_str(vm));
+    if context.is_none() {
+        log::warn!("expected a vm: server, client or ui");
+    }
+    context
+}
+
+#[rrplug::concommand]
+fn dbgq_pause(command: CCommandResult) {
+    if let Some(context) = context_from_arg(&command) {
+        PLUGIN
+            .wait()
+            .debug_info
+            .get(context)
+            .pause(PauseReason::Requested);
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_run(command: CCommandResult) {
+    if let Some(context) = context_from_arg(&command) {
+        PLUGIN.wait().debug_info.get(context).unpause();
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_step(command: CCommandResult) {
+    if let Some(context) = context_from_arg(&command) {
+        PLUGIN.wait().debug_info.get(context).resume();
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_break_next(command: CCommandResult) {
+    if let Some(context) = context_from_arg(&command) {
+        PLUGIN.wait().debug_info.get(context).break_on_next_call();
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_run_to(command: CCommandResult) {
+    let args = command.get_args();
+    let Some(function) = args.first() else {
+        log::warn!("usage: dbgq_run_to <func> [vm]");
+        return;
+    };
+    let Some(contexts) = contexts_from_arg(args.get(1)) else {
+        log::warn!("expected a vm: server, client or ui");
+        return;
+    };
+
+    for context in contexts {
+        PLUGIN
+            .wait()
+            .debug_info
+            .get(context)
+            .run_to(function.clone());
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_pause_all(_command: CCommandResult) {
+    PLUGIN.wait().pause_all(PauseReason::Requested);
+}
+
+#[rrplug::concommand]
+fn dbgq_run_all(_command: CCommandResult) {
+    PLUGIN.wait().unpause_all();
+}
+
+#[rrplug::concommand]
+fn dbgq_step_all(_command: CCommandResult) {
+    PLUGIN.wait().step_all();
+}
+
+#[rrplug::concommand]
+fn dbgq_break(command: CCommandResult) {
+    let args = command.get_args();
+    let Some(function) = args.first() else {
+        log::warn!("usage: dbgq_break <func> [vm]");
+        return;
+    };
+    let Some(contexts) = contexts_from_arg(args.get(1)) else {
+        log::warn!("expected a vm: server, client or ui");
+        return;
+    };
+
+    for context in contexts {
+        PLUGIN
+            .wait()
+            .debug_info
+            .get(context)
+            .add_breakpoint(function.clone());
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_break_load(command: CCommandResult) {
+    add_script_breakpoint(command, ScriptBreakKind::Load, "dbgq_break_load")
+}
+
+#[rrplug::concommand]
+fn dbgq_break_first_call(command: CCommandResult) {
+    add_script_breakpoint(command, ScriptBreakKind::FirstCall, "dbgq_break_first_call")
+}
+
+fn add_script_breakpoint(command: CCommandResult, kind: ScriptBreakKind, name: &str) {
+    let args = command.get_args();
+    let Some(pattern) = args.first() else {
+        log::warn!("usage: {name} <glob> [vm]");
+        return;
+    };
+    let Some(contexts) = contexts_from_arg(args.get(1)) else {
+        log::warn!("expected a vm: server, client or ui");
+        return;
+    };
+
+    for context in contexts {
+        if let Err(err) = PLUGIN
+            .wait()
+            .debug_info
+            .get(context)
+            .add_script_breakpoint(pattern, kind)
+        {
+            log::warn!("{pattern} is not a valid glob: {err}");
+            return;
+        }
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_break_value(command: CCommandResult) {
+    let args = command.get_args();
+    let Some(predicate) = args.first() else {
+        log::warn!("usage: dbgq_break_value <value|=value|min..max|/regex/> [vm]");
+        return;
+    };
+    let predicate = match predicate.parse::<DataPredicate>() {
+        Ok(predicate) => predicate,
+        Err(err) => {
+            log::warn!("{err}");
+            return;
+        }
+    };
+    let Some(contexts) = contexts_from_arg(args.get(1)) else {
+        log::warn!("expected a vm: server, client or ui");
+        return;
+    };
+
+    for context in contexts {
+        PLUGIN
+            .wait()
+            .debug_info
+            .get(context)
+            .add_data_breakpoint(predicate.clone());
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_skip(command: CCommandResult) {
+    let args = command.get_args();
+    let Some(skipped) = args.first() else {
+        log::warn!("usage: dbgq_skip <func|file.nut> [vm]");
+        return;
+    };
+    let Some(contexts) = contexts_from_arg(args.get(1)) else {
+        log::warn!("expected a vm: server, client or ui");
+        return;
+    };
+
+    let is_file = skipped.ends_with(".nut") || skipped.ends_with(".gnut");
+    for context in contexts {
+        PLUGIN
+            .wait()
+            .debug_info
+            .get(context)
+            .update_skip_list(|skip_list| {
+                if is_file {
+                    skip_list.files.push(skipped.clone());
+                } else {
+                    skip_list.functions.insert(skipped.clone());
+                }
+            });
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_clear(command: CCommandResult) {
+    let Some(contexts) = contexts_from_arg(command.get_args().first()) else {
+        log::warn!("expected a vm: server, client or ui");
+        return;
+    };
+
+    for context in contexts {
+        PLUGIN.wait().debug_info.get(context).clear_breakpoints();
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_dump(command: CCommandResult) {
+    let plugin = PLUGIN.wait();
+    let file = command
+        .get_args()
+        .first()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| plugin.settings.dump_path.lock().clone());
+
+    match plugin.recording.dump(&file) {
+        Ok(()) => log::info!("dumped the trace to {}", file.display()),
+        Err(err) => log::error!("failed to dump the trace to {}: {err}", file.display()),
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_hooks(command: CCommandResult) {
+    let enabled = match command.get_args().first().map(|arg| arg.as_str()) {
+        Some("on" | "1") => true,
+        Some("off" | "0") => false,
+        _ => {
+            log::warn!("usage: dbgq_hooks <on|off>");
+            return;
+        }
+    };
+
+    let settings = &PLUGIN.wait().settings;
+    settings.hooks_enabled.store(enabled, Ordering::Relaxed);
+    settings.hooks_dirty.store(true, Ordering::Relaxed);
+}
+
+#[rrplug::concommand]
+fn dbgq_reload_config(_command: CCommandResult) {
+    if let Err(err) = reload_config(PLUGIN.wait()) {
+        log::error!("{err}");
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_session_export(command: CCommandResult) {
+    let Some(file) = command.get_args().first() else {
+        log::warn!("usage: dbgq_session_export <file>");
+        return;
+    };
+
+    match Session::capture(PLUGIN.wait()).save(Path::new(file)) {
+        Ok(()) => log::info!("exported the session to {file}"),
+        Err(err) => log::error!("{err}"),
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_session_import(command: CCommandResult) {
+    let Some(file) = command.get_args().first() else {
+        log::warn!("usage: dbgq_session_import <file>");
+        return;
+    };
+
+    match Session::load(Path::new(file)) {
+        Ok(session) => {
+            session.apply(PLUGIN.wait());
+            log::info!("imported the session from {file}");
+        }
+        Err(err) => log::error!("{err}"),
+    }
+}
+
+#[rrplug::concommand]
+fn dbgq_shutdown(_command: CCommandResult) {
+    // concommands are only ran on the engine thread
+    PLUGIN
+        .wait()
+        .shutdown(unsafe { EngineToken::new_unchecked() });
+}
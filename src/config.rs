@@ -0,0 +1,647 @@
+use glob::Pattern;
+use rrplug::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use crate::{
+    context_from_str,
+    convars::{DEFAULT_DUMP_PATH, DEFAULT_LOG_LIMIT},
+    crashdump::DEFAULT_CRASH_DUMP_EVENTS,
+    databreak::DataPredicate,
+    debug::{ConfiguredBreakpoints, ScriptBreakKind},
+    guard::poisoned_hooks,
+    hooks::HOOK_NAMES,
+    stacktrace::StackTrace,
+    DebugPlugin, ALL_CONTEXTS,
+};
+
+const DEFAULT_CONFIG_PATH: &str = "R2Northstar/plugins/dbgquirrel.toml";
+/// only used while the engine's own network timeout can't be read
+const DEFAULT_NETWORK_TIMEOUT_MS: usize = 30_000;
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub debugger: DebuggerConfig,
+    pub hooks: HooksConfig,
+    pub breakpoints: BreakpointsConfig,
+    pub script_breakpoints: ScriptBreakpointsConfig,
+    pub data_breakpoints: DataBreakpointsConfig,
+    /// left out the filters of the last session are kept
+    pub filters: Option<Filters>,
+    pub sinks: SinksConfig,
+    pub watchdog: WatchdogConfig,
+    pub pause_timeout: PauseTimeoutConfig,
+    pub skip: SkipConfig,
+    pub ui: UiConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebuggerConfig {
+    pub break_on_error: bool,
+    pub logging: bool,
+    pub log_limit: usize,
+    pub dump_path: PathBuf,
+    pub crash_dump_events: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub enabled: bool,
+    /// names of the detours that should stay disabled like `sq_pushobject`
+    pub disabled: Vec<String>,
+    /// the vms that get traced, the others only run through the original functions
+    pub vms: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BreakpointsConfig {
+    pub server: Vec<String>,
+    pub client: Vec<String>,
+    pub ui: Vec<String>,
+}
+
+/// globs matched against script paths like `scripts/vscripts/client/cl_*.gnut`
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptBreakpoints {
+    /// stops before a matching script is compiled
+    pub load: Vec<String>,
+    /// stops on the first call into any function of a matching script
+    pub first_call: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptBreakpointsConfig {
+    pub server: ScriptBreakpoints,
+    pub client: ScriptBreakpoints,
+    pub ui: ScriptBreakpoints,
+}
+
+impl ScriptBreakpointsConfig {
+    pub fn get(&self, context: ScriptContext) -> &ScriptBreakpoints {
+        match context {
+            ScriptContext::SERVER => &self.server,
+            ScriptContext::CLIENT => &self.client,
+            ScriptContext::UI => &self.ui,
+        }
+    }
+}
+
+/// predicates for the values of the push and get hooks like `=mp_titan`, `0..10` or `/^weapon_/`
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataBreakpointsConfig {
+    pub server: Vec<String>,
+    pub client: Vec<String>,
+    pub ui: Vec<String>,
+}
+
+impl DataBreakpointsConfig {
+    pub fn get(&self, context: ScriptContext) -> &[String] {
+        match context {
+            ScriptContext::SERVER => &self.server,
+            ScriptContext::CLIENT => &self.client,
+            ScriptContext::UI => &self.ui,
+        }
+    }
+}
+
+/// decides what reaches the trace
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filters {
+    pub hide_functions: HashSet<String>,
+    pub hide_values: bool,
+}
+
+/// code that is never stopped in, like think functions that run every frame
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SkipList {
+    pub functions: HashSet<String>,
+    /// matched against the last parts of the script path so `cl_hud.gnut` is enough, `hud.gnut` is not
+    pub files: Vec<String>,
+    /// also leave everything inside skipped code out of the trace
+    pub hide: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SkipConfig {
+    pub server: SkipList,
+    pub client: SkipList,
+    pub ui: SkipList,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SinksConfig {
+    /// every event is also appended to this file
+    pub file: Option<PathBuf>,
+    /// every event is also printed to the game console
+    pub console: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogConfig {
+    /// how long a single `sq_call` may run before it's reported, 0 turns the watchdog off
+    pub threshold_ms: usize,
+    /// pause the vm at its next hook once it's reported
+    pub pause: bool,
+}
+
+/// how long each vm may stay paused before it runs again, 0 waits forever
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PauseTimeoutConfig {
+    pub server_ms: usize,
+    pub client_ms: usize,
+    pub ui_ms: usize,
+    /// a paused server stops answering clients so it shouldn't wait past their timeout
+    pub server_network_timeout: bool,
+    /// the fallback for when the engine's network timeout can't be read
+    pub network_timeout_ms: usize,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    /// skip the window and only serve the remote endpoint, left out it's detected
+    pub headless: Option<bool>,
+    pub title: String,
+    pub width: f32,
+    pub height: f32,
+    pub default_context: String,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl Default for DebuggerConfig {
+    fn default() -> Self {
+        Self {
+            break_on_error: false,
+            logging: true,
+            log_limit: DEFAULT_LOG_LIMIT,
+            dump_path: PathBuf::from(DEFAULT_DUMP_PATH),
+            crash_dump_events: DEFAULT_CRASH_DUMP_EVENTS,
+        }
+    }
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            disabled: Vec::new(),
+            vms: vec!["server".to_string(), "client".to_string(), "ui".to_string()],
+        }
+    }
+}
+
+impl Default for PauseTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            server_ms: 0,
+            client_ms: 0,
+            ui_ms: 0,
+            server_network_timeout: true,
+            network_timeout_ms: DEFAULT_NETWORK_TIMEOUT_MS,
+        }
+    }
+}
+
+impl PauseTimeoutConfig {
+    /// the network timeout caps the server even if it has no timeout of its own
+    ///
+    /// `engine_timeout_ms` is the engine's network timeout if it could be read, `network_timeout_ms` stands in for it otherwise
+    pub fn max_pause_ms(&self, context: ScriptContext, engine_timeout_ms: Option<usize>) -> usize {
+        let network_timeout_ms = engine_timeout_ms.unwrap_or(self.network_timeout_ms);
+        match context {
+            ScriptContext::SERVER if self.server_network_timeout => match self.server_ms {
+                0 => network_timeout_ms,
+                ms => ms.min(network_timeout_ms),
+            },
+            ScriptContext::SERVER => self.server_ms,
+            ScriptContext::CLIENT => self.client_ms,
+            ScriptContext::UI => self.ui_ms,
+        }
+    }
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            headless: None,
+            title: "Debugger :3".to_string(),
+            width: 500.,
+            height: 400.,
+            default_context: "ui".to_string(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => {
+                f.write_fmt(format_args!("couldn't read {}: {err}", path.display()))
+            }
+            ConfigError::Parse(path, err) => {
+                f.write_fmt(format_args!("{} is not valid: {err}", path.display()))
+            }
+            ConfigError::Invalid(path, err) => {
+                f.write_fmt(format_args!("{} is not valid: {err}", path.display()))
+            }
+        }
+    }
+}
+
+impl Filters {
+    pub fn allows(&self, trace: &StackTrace) -> bool {
+        match trace {
+            StackTrace::Call { name, .. } => name
+                .as_str()
+                .map(|name| !self.hide_functions.contains(name))
+                .unwrap_or(true),
+            StackTrace::Pushed(_) | StackTrace::Aquired(_) => !self.hide_values,
+            _ => true,
+        }
+    }
+}
+
+impl SkipList {
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.files.is_empty()
+    }
+
+    pub fn skips(&self, function: Option<&str>, file: Option<&str>) -> bool {
+        function.is_some_and(|function| self.functions.contains(function))
+            || file.is_some_and(|file| {
+                self.files
+                    .iter()
+                    .any(|skipped| !skipped.is_empty() && Path::new(file).ends_with(skipped))
+            })
+    }
+}
+
+impl UiConfig {
+    pub fn default_context(&self) -> ScriptContext {
+        context_from_str(&self.default_context).unwrap_or(ScriptContext::UI)
+    }
+}
+
+/// can be overwritten with `-dbgq_config <path>`
+pub fn config_path() -> PathBuf {
+    let args = std::env::args().collect::<Vec<String>>();
+    args.iter()
+        .position(|arg| arg == "-dbgq_config")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH))
+}
+
+impl Config {
+    /// a missing file is not an error since everything has a default
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(ConfigError::Io(path.to_path_buf(), err)),
+        };
+
+        let config: Self =
+            toml::from_str(&source).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))?;
+        config
+            .validate()
+            .map_err(|err| ConfigError::Invalid(path.to_path_buf(), err))?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.debugger.log_limit == 0 {
+            return Err("debugger.log_limit has to be at least 1".to_string());
+        }
+
+        if let Some(hook) = self
+            .hooks
+            .disabled
+            .iter()
+            .find(|hook| !HOOK_NAMES.contains(&hook.as_str()))
+        {
+            return Err(format!(
+                "hooks.disabled has an unknown hook {hook}; expected one of {}",
+                HOOK_NAMES.join(", ")
+            ));
+        }
+
+        if let Some(vm) = self
+            .hooks
+            .vms
+            .iter()
+            .find(|vm| context_from_str(vm).is_none())
+        {
+            return Err(format!(
+                "hooks.vms has an unknown vm {vm}; expected server, client or ui"
+            ));
+        }
+
+        if let Some((pattern, err)) = ALL_CONTEXTS
+            .into_iter()
+            .map(|context| self.script_breakpoints.get(context))
+            .flat_map(|breakpoints| breakpoints.load.iter().chain(&breakpoints.first_call))
+            .find_map(|pattern| Pattern::new(pattern).err().map(|err| (pattern, err)))
+        {
+            return Err(format!(
+                "script_breakpoints has an invalid glob {pattern}: {err}"
+            ));
+        }
+
+        if let Some(err) = ALL_CONTEXTS
+            .into_iter()
+            .flat_map(|context| self.data_breakpoints.get(context))
+            .find_map(|predicate| predicate.parse::<DataPredicate>().err())
+        {
+            return Err(format!("data_breakpoints: {err}"));
+        }
+
+        if self.pause_timeout.server_network_timeout && self.pause_timeout.network_timeout_ms == 0 {
+            return Err(
+                "pause_timeout.network_timeout_ms has to be positive when server_network_timeout is on"
+                    .to_string(),
+            );
+        }
+
+        if context_from_str(&self.ui.default_context).is_none() {
+            return Err(format!(
+                "ui.default_context is {} but has to be server, client or ui",
+                self.ui.default_context
+            ));
+        }
+
+        if self.ui.width <= 0. || self.ui.height <= 0. {
+            return Err("ui.width and ui.height have to be positive".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// hook changes are only picked up on the next frame since they need the engine thread
+    pub fn apply(&self, plugin: &DebugPlugin) {
+        let settings = &plugin.settings;
+        settings
+            .break_on_error
+            .store(self.debugger.break_on_error, Ordering::Relaxed);
+        settings
+            .logging
+            .store(self.debugger.logging, Ordering::Relaxed);
+        *settings.dump_path.lock() = self.debugger.dump_path.clone();
+        settings
+            .crash_dump_events
+            .store(self.debugger.crash_dump_events, Ordering::Relaxed);
+        if let Some(filters) = &self.filters {
+            *settings.filters.lock() = filters.clone();
+        }
+
+        settings
+            .hooks_enabled
+            .store(self.hooks.enabled, Ordering::Relaxed);
+        // a hook that panicked stays off even if the config doesn't list it
+        *settings.disabled_hooks.lock() = self
+            .hooks
+            .disabled
+            .iter()
+            .cloned()
+            .chain(poisoned_hooks().map(str::to_string))
+            .collect();
+        settings.hooks_dirty.store(true, Ordering::Relaxed);
+
+        settings
+            .watchdog_threshold_ms
+            .store(self.watchdog.threshold_ms, Ordering::Relaxed);
+        settings
+            .watchdog_pause
+            .store(self.watchdog.pause, Ordering::Relaxed);
+
+        plugin.recording.set_limit(self.debugger.log_limit);
+        plugin
+            .recording
+            .set_sinks(self.sinks.file.as_deref(), self.sinks.console);
+
+        let hooked_vms = self
+            .hooks
+            .vms
+            .iter()
+            .filter_map(|vm| context_from_str(vm))
+            .collect::<Vec<ScriptContext>>();
+
+        *plugin.settings.pause_timeout.lock() = self.pause_timeout.clone();
+        plugin.apply_pause_timeouts();
+
+        for context in ALL_CONTEXTS {
+            plugin
+                .debug_info
+                .get(context)
+                .hooks_enabled
+                .store(hooked_vms.contains(&context), Ordering::Relaxed);
+
+            let breakpoints = match context {
+                ScriptContext::SERVER => &self.breakpoints.server,
+                ScriptContext::CLIENT => &self.breakpoints.client,
+                ScriptContext::UI => &self.breakpoints.ui,
+            };
+
+            // the globs and predicates were validated on load
+            let script_breakpoints = self.script_breakpoints.get(context);
+            let state = plugin.debug_info.get(context);
+            state.replace_configured(ConfiguredBreakpoints {
+                functions: breakpoints.clone(),
+                scripts: script_breakpoints
+                    .load
+                    .iter()
+                    .map(|pattern| (pattern.clone(), ScriptBreakKind::Load))
+                    .chain(
+                        script_breakpoints
+                            .first_call
+                            .iter()
+                            .map(|pattern| (pattern.clone(), ScriptBreakKind::FirstCall)),
+                    )
+                    .collect(),
+                values: self
+                    .data_breakpoints
+                    .get(context)
+                    .iter()
+                    .filter_map(|predicate| predicate.parse().ok())
+                    .collect(),
+            });
+
+            state.set_skip_list(match context {
+                ScriptContext::SERVER => self.skip.server.clone(),
+                ScriptContext::CLIENT => self.skip.client.clone(),
+                ScriptContext::UI => self.skip.ui.clone(),
+            });
+        }
+    }
+}
+
+pub fn reload_config(plugin: &DebugPlugin) -> Result<(), ConfigError> {
+    let path = config_path();
+    Config::load(&path)?.apply(plugin);
+    log::info!("reloaded {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stacktrace::{CallArgs, FunctionName, InlineStr, TraceValue};
+
+    fn parse(source: &str) -> Config {
+        toml::from_str(source).expect("the test config should parse")
+    }
+
+    fn call(name: FunctionName) -> StackTrace {
+        StackTrace::Call {
+            name,
+            args: CallArgs::default(),
+            depth: 0,
+        }
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(Config::default().validate(), Ok(()));
+        assert_eq!(parse("").validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_bad_values() {
+        for source in [
+            "debugger.log_limit = 0",
+            "hooks.disabled = [\"sq_nothing\"]",
+            "hooks.vms = [\"lobby\"]",
+            "script_breakpoints.client.load = [\"[cl_hud\"]",
+            "data_breakpoints.server = [\"5..1\"]",
+            "data_breakpoints.server = [\"/(/\"]",
+            "pause_timeout = { server_network_timeout = true, network_timeout_ms = 0 }",
+            "ui.default_context = \"lobby\"",
+            "ui.width = 0",
+        ] {
+            assert!(
+                parse(source).validate().is_err(),
+                "{source} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn validate_accepts_known_names() {
+        let config = parse(
+            r#"
+            hooks.disabled = ["sq_pushobject"]
+            hooks.vms = ["SERVER", "cl"]
+            script_breakpoints.client.first_call = ["*/client/cl_*.gnut"]
+            data_breakpoints.server = ["=mp_titan", "1..5", "/^weapon_/"]
+            ui.default_context = "server"
+            "#,
+        );
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn network_timeout_caps_the_server() {
+        let pause_timeout =
+            parse("pause_timeout = { server_ms = 60000, client_ms = 5000 }").pause_timeout;
+        assert_eq!(
+            pause_timeout.max_pause_ms(ScriptContext::SERVER, None),
+            DEFAULT_NETWORK_TIMEOUT_MS
+        );
+        assert_eq!(
+            pause_timeout.max_pause_ms(ScriptContext::SERVER, Some(45_000)),
+            45_000
+        );
+        assert_eq!(
+            pause_timeout.max_pause_ms(ScriptContext::CLIENT, Some(45_000)),
+            5000
+        );
+
+        let pause_timeout =
+            parse("pause_timeout = { server_network_timeout = false }").pause_timeout;
+        assert_eq!(
+            pause_timeout.max_pause_ms(ScriptContext::SERVER, Some(45_000)),
+            0
+        );
+    }
+
+    #[test]
+    fn skip_list_matches_whole_path_parts() {
+        let skip_list = SkipList {
+            functions: HashSet::from(["UpdateMainHudVisibility".to_string()]),
+            files: vec![
+                "cl_hud.gnut".to_string(),
+                "client/cl_minimap.gnut".to_string(),
+                String::new(),
+            ],
+            hide: false,
+        };
+
+        assert!(skip_list.skips(Some("UpdateMainHudVisibility"), None));
+        assert!(!skip_list.skips(Some("UpdateMainHud"), None));
+        assert!(skip_list.skips(None, Some("scripts/vscripts/client/cl_hud.gnut")));
+        assert!(skip_list.skips(None, Some("cl_hud.gnut")));
+        assert!(!skip_list.skips(None, Some("scripts/vscripts/client/xcl_hud.gnut")));
+        assert!(skip_list.skips(
+            Some("OnDamaged"),
+            Some("scripts/vscripts/client/cl_minimap.gnut")
+        ));
+        assert!(!skip_list.skips(None, Some("scripts/vscripts/ui/cl_minimap.gnut")));
+        assert!(!skip_list.skips(None, None));
+    }
+
+    #[test]
+    fn filters_hide_functions() {
+        let filters = Filters {
+            hide_functions: HashSet::from(["CodeCallback_Think".to_string()]),
+            hide_values: false,
+        };
+
+        assert!(!filters.allows(&call(FunctionName::Script(InlineStr::new(
+            "CodeCallback_Think"
+        )))));
+        assert!(!filters.allows(&call(FunctionName::Native(InlineStr::new(
+            "CodeCallback_Think"
+        )))));
+        assert!(filters.allows(&call(FunctionName::Script(InlineStr::new("OnDamaged")))));
+        assert!(filters.allows(&call(FunctionName::Unknown)));
+        assert!(filters.allows(&StackTrace::Pushed(TraceValue::Integer(1))));
+    }
+
+    #[test]
+    fn filters_hide_values() {
+        let filters = Filters {
+            hide_functions: HashSet::new(),
+            hide_values: true,
+        };
+
+        assert!(!filters.allows(&StackTrace::Pushed(TraceValue::Integer(1))));
+        assert!(!filters.allows(&StackTrace::Aquired(TraceValue::Integer(1))));
+        assert!(filters.allows(&call(FunctionName::Script(InlineStr::new("OnDamaged")))));
+        assert!(filters.allows(&StackTrace::Misc("paused".to_string())));
+    }
+}
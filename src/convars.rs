@@ -0,0 +1,284 @@
+use parking_lot::Mutex;
+use rrplug::{
+    high::convars::{ConVarRegister, ConVarStruct},
+    prelude::*,
+};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{
+    config::{Filters, PauseTimeoutConfig},
+    crashdump::DEFAULT_CRASH_DUMP_EVENTS,
+    exports::PLUGIN,
+    guard::poisoned_hooks,
+    hooks::HOOK_NAMES,
+};
+
+pub const DEFAULT_DUMP_PATH: &str = "dbgquirrel_trace.txt";
+pub const DEFAULT_LOG_LIMIT: usize = 10_000;
+
+/// everything that can be changed live through the `dbgq_*` convars
+pub struct Settings {
+    pub break_on_error: AtomicBool,
+    /// when off hooks skip building trace events and only check for pauses
+    pub logging: AtomicBool,
+    pub hooks_enabled: AtomicBool,
+    pub disabled_hooks: Mutex<HashSet<String>>,
+    /// set when the hooks have to be toggled on the next engine frame
+    pub hooks_dirty: AtomicBool,
+    pub dump_path: Mutex<PathBuf>,
+    pub filters: Mutex<Filters>,
+    /// 0 turns the watchdog off
+    pub watchdog_threshold_ms: AtomicUsize,
+    /// pause a vm once the watchdog reports it
+    pub watchdog_pause: AtomicBool,
+    /// how many events of each vm end up in a crash dump
+    pub crash_dump_events: AtomicUsize,
+    pub pause_timeout: Mutex<PauseTimeoutConfig>,
+    /// the engine's network timeout once it was read, 0 until then
+    pub engine_network_timeout_ms: AtomicUsize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            break_on_error: AtomicBool::new(false),
+            logging: AtomicBool::new(true),
+            hooks_enabled: AtomicBool::new(true),
+            disabled_hooks: Mutex::new(HashSet::new()),
+            hooks_dirty: AtomicBool::new(false),
+            dump_path: Mutex::new(PathBuf::from(DEFAULT_DUMP_PATH)),
+            filters: Mutex::new(Filters::default()),
+            watchdog_threshold_ms: AtomicUsize::new(0),
+            watchdog_pause: AtomicBool::new(false),
+            crash_dump_events: AtomicUsize::new(DEFAULT_CRASH_DUMP_EVENTS),
+            pause_timeout: Mutex::new(PauseTimeoutConfig::default()),
+            engine_network_timeout_ms: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// each one is registered on its own so a single failure doesn't take the others down with it
+struct DebugConVars {
+    break_on_error: Option<ConVarStruct>,
+    logging: Option<ConVarStruct>,
+    hooks_enabled: Option<ConVarStruct>,
+    disabled_hooks: Option<ConVarStruct>,
+    log_limit: Option<ConVarStruct>,
+    dump_path: Option<ConVarStruct>,
+    watchdog_ms: Option<ConVarStruct>,
+}
+
+static CONVARS: EngineGlobal<RefCell<Option<DebugConVars>>> = EngineGlobal::new(RefCell::new(None));
+
+/// how long clients wait on a server that stopped answering, in seconds
+const NETWORK_TIMEOUT_CONVAR: &str = "cl_timeout";
+/// looked up once, `None` inside if the engine doesn't have it
+static NETWORK_TIMEOUT: EngineGlobal<RefCell<Option<Option<ConVarStruct>>>> =
+    EngineGlobal::new(RefCell::new(None));
+
+pub fn register_convars(engine_token: EngineToken) {
+    let convars = try_register_convars(engine_token);
+    _ = CONVARS.get(engine_token).replace(Some(convars));
+}
+
+fn try_register_convars(engine_token: EngineToken) -> DebugConVars {
+    macro_rules! register {
+        ($name:literal, $default:expr, $help:literal, $callback:ident $(,)?) => {
+            ConVarStruct::try_new(
+                &ConVarRegister {
+                    callback: Some($callback),
+                    ..ConVarRegister::mandatory($name, $default, 0, $help)
+                },
+                engine_token,
+            )
+            .map_err(|err| log::error!("failed to register {}: {err}", $name))
+            .ok()
+        };
+    }
+
+    DebugConVars {
+        break_on_error: register!(
+            "dbgq_break_on_error",
+            "0",
+            "pauses the vm when a script error is raised",
+            dbgq_break_on_error_changed,
+        ),
+        logging: register!(
+            "dbgq_logging",
+            "1",
+            "records every hooked call into the trace",
+            dbgq_logging_changed,
+        ),
+        hooks_enabled: register!(
+            "dbgq_hooks_enabled",
+            "1",
+            "enables every squirrel hook",
+            dbgq_hooks_enabled_changed,
+        ),
+        disabled_hooks: register!(
+            "dbgq_disabled_hooks",
+            "",
+            "hooks that stay off while the others are enabled, like \"sq_pushobject sq_getobject\"",
+            dbgq_disabled_hooks_changed,
+        ),
+        log_limit: register!(
+            "dbgq_log_limit",
+            &DEFAULT_LOG_LIMIT.to_string(),
+            "how many trace events are kept per vm",
+            dbgq_log_limit_changed,
+        ),
+        dump_path: register!(
+            "dbgq_dump_path",
+            DEFAULT_DUMP_PATH,
+            "where dbgq_dump writes the trace when no file is given",
+            dbgq_dump_path_changed,
+        ),
+        watchdog_ms: register!(
+            "dbgq_watchdog_ms",
+            "0",
+            "reports sq_calls running longer than this, 0 turns the watchdog off",
+            dbgq_watchdog_ms_changed,
+        ),
+    }
+}
+
+/// the engine's network timeout, `None` if it doesn't have one or it's turned off
+pub fn engine_network_timeout_ms(engine_token: EngineToken) -> Option<usize> {
+    let mut convar = NETWORK_TIMEOUT.get(engine_token).borrow_mut();
+    let convar = convar.get_or_insert_with(|| {
+        ConVarStruct::find_convar_by_name(NETWORK_TIMEOUT_CONVAR, engine_token)
+            .map_err(|_| {
+                log::warn!("{NETWORK_TIMEOUT_CONVAR} wasn't found, the server pause timeout falls back to network_timeout_ms")
+            })
+            .ok()
+    });
+
+    let seconds = convar.as_ref()?.get_value_f32();
+    (seconds > 0.0).then(|| (seconds * 1000.0) as usize)
+}
+
+/// splits a list of hook names on commas and whitespace, unknown names are logged and left out
+fn parse_hook_list(list: &str) -> HashSet<String> {
+    list.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|hook| !hook.is_empty())
+        .filter(|hook| {
+            let known = HOOK_NAMES.contains(hook);
+            if !known {
+                log::warn!("dbgq_disabled_hooks: unknown hook {hook}");
+            }
+            known
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+fn with_convars(callback: impl FnOnce(&DebugConVars)) {
+    // convar callbacks are only ran on the engine thread
+    let engine_token = unsafe { EngineToken::new_unchecked() };
+    if let Some(convars) = CONVARS.get(engine_token).borrow().as_ref() {
+        callback(convars)
+    }
+}
+
+#[rrplug::convar]
+fn dbgq_break_on_error_changed(_old_value: String, _float_old_value: f32) {
+    with_convars(|convars| {
+        if let Some(convar) = &convars.break_on_error {
+            PLUGIN
+                .wait()
+                .settings
+                .break_on_error
+                .store(convar.get_value_i32() != 0, Ordering::Relaxed)
+        }
+    })
+}
+
+#[rrplug::convar]
+fn dbgq_logging_changed(_old_value: String, _float_old_value: f32) {
+    with_convars(|convars| {
+        if let Some(convar) = &convars.logging {
+            PLUGIN
+                .wait()
+                .settings
+                .logging
+                .store(convar.get_value_i32() != 0, Ordering::Relaxed)
+        }
+    })
+}
+
+#[rrplug::convar]
+fn dbgq_hooks_enabled_changed(_old_value: String, _float_old_value: f32) {
+    with_convars(|convars| {
+        if let Some(convar) = &convars.hooks_enabled {
+            let settings = &PLUGIN.wait().settings;
+            settings
+                .hooks_enabled
+                .store(convar.get_value_i32() != 0, Ordering::Relaxed);
+            settings.hooks_dirty.store(true, Ordering::Relaxed);
+        }
+    })
+}
+
+#[rrplug::convar]
+fn dbgq_disabled_hooks_changed(_old_value: String, _float_old_value: f32) {
+    with_convars(|convars| {
+        let Some(Ok(list)) = convars
+            .disabled_hooks
+            .as_ref()
+            .map(|convar| convar.get_value_str())
+        else {
+            return;
+        };
+
+        // a hook that panicked stays off no matter what the convar says
+        let mut disabled = parse_hook_list(&list);
+        disabled.extend(poisoned_hooks().map(str::to_string));
+
+        let settings = &PLUGIN.wait().settings;
+        *settings.disabled_hooks.lock() = disabled;
+        settings.hooks_dirty.store(true, Ordering::Relaxed);
+    })
+}
+
+#[rrplug::convar]
+fn dbgq_log_limit_changed(_old_value: String, _float_old_value: f32) {
+    with_convars(|convars| {
+        if let Some(convar) = &convars.log_limit {
+            PLUGIN
+                .wait()
+                .recording
+                .set_limit(convar.get_value_i32().max(0) as usize)
+        }
+    })
+}
+
+#[rrplug::convar]
+fn dbgq_dump_path_changed(_old_value: String, _float_old_value: f32) {
+    with_convars(|convars| {
+        if let Some(Ok(path)) = convars
+            .dump_path
+            .as_ref()
+            .map(|convar| convar.get_value_str())
+        {
+            *PLUGIN.wait().settings.dump_path.lock() = PathBuf::from(path);
+        }
+    })
+}
+
+#[rrplug::convar]
+fn dbgq_watchdog_ms_changed(_old_value: String, _float_old_value: f32) {
+    with_convars(|convars| {
+        if let Some(convar) = &convars.watchdog_ms {
+            PLUGIN
+                .wait()
+                .settings
+                .watchdog_threshold_ms
+                .store(convar.get_value_i32().max(0) as usize, Ordering::Relaxed)
+        }
+    })
+}
@@ -0,0 +1,140 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{exports::PLUGIN, ALL_CONTEXTS};
+
+const CRASH_DUMP_DIR: &str = "R2Northstar/logs";
+pub const DEFAULT_CRASH_DUMP_EVENTS: usize = 200;
+
+/// keeps dumps written in the same millisecond apart
+static DUMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// writes what every vm was doing right before the crash into `R2Northstar/logs/dbgquirrel_crash_<time>_<n>.txt`
+///
+/// `recovered` marks panics that a hook caught, the game kept running after those
+///
+/// the crashing thread may hold any of our locks so nothing here waits on one
+///
+/// only what is already recorded ends up in the dump, the queued events are left for the window
+/// since the game keeps running after a recovered panic and a second panic in here would abort it
+pub fn write_crash_dump(cause: &str, recovered: bool) {
+    let Some(plugin) = PLUGIN.get() else {
+        return;
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    let count = DUMP_COUNT.fetch_add(1, Ordering::Relaxed);
+    let path =
+        PathBuf::from(CRASH_DUMP_DIR).join(format!("dbgquirrel_crash_{timestamp}_{count}.txt"));
+
+    let result = File::create(&path).and_then(|mut file| {
+        writeln!(file, "cause: {cause}")?;
+        if recovered {
+            writeln!(
+                file,
+                "recovered: the hook was disabled and the game kept running"
+            )?;
+        }
+
+        let events = plugin.settings.crash_dump_events.load(Ordering::Relaxed);
+        for context in ALL_CONTEXTS {
+            let state = plugin.debug_info.get(context);
+            writeln!(file, "\n==== {context:?} ====")?;
+            writeln!(
+                file,
+                "alive: {}, generation: {}, call depth: {}",
+                state.alive.load(Ordering::Relaxed),
+                state.generation.load(Ordering::Relaxed),
+                state.call_depth.load(Ordering::Relaxed)
+            )?;
+
+            match state.run_state.try_lock() {
+                Some(run_state) => writeln!(file, "state: {run_state}")?,
+                None => writeln!(file, "state: <locked>")?,
+            }
+
+            match state.breakpoints.try_lock() {
+                Some(breakpoints) => {
+                    write!(file, "breakpoints:")?;
+                    for breakpoint in breakpoints.iter() {
+                        write!(file, " {breakpoint}")?;
+                    }
+                    writeln!(file)?
+                }
+                None => writeln!(file, "breakpoints: <locked>")?,
+            }
+
+            writeln!(file, "last {events} events:")?;
+            match plugin.recording.try_last(context, events) {
+                Some(lines) => {
+                    for line in lines {
+                        writeln!(file, "{line}")?;
+                    }
+                }
+                None => writeln!(file, "<the trace was locked>")?,
+            }
+        }
+
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => log::error!("wrote a crash dump to {}", path.display()),
+        Err(err) => log::error!("failed to write a crash dump to {}: {err}", path.display()),
+    }
+}
+
+/// dumps on native crashes too, the previous filter still gets to run after us
+#[cfg(windows)]
+pub fn install_exception_handler() {
+    let previous = unsafe { seh::SetUnhandledExceptionFilter(Some(seh::on_exception)) };
+    _ = seh::PREVIOUS.set(previous);
+}
+
+/// puts the previous filter back, the process would jump into an unloaded dll otherwise
+#[cfg(windows)]
+pub fn uninstall_exception_handler() {
+    if let Some(previous) = seh::PREVIOUS.get() {
+        unsafe { seh::SetUnhandledExceptionFilter(*previous) };
+    }
+}
+
+#[cfg(windows)]
+mod seh {
+    use std::{ffi::c_void, sync::OnceLock};
+
+    use super::write_crash_dump;
+
+    pub type ExceptionFilter = Option<unsafe extern "system" fn(*const c_void) -> i32>;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn SetUnhandledExceptionFilter(filter: ExceptionFilter) -> ExceptionFilter;
+    }
+
+    const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+    pub static PREVIOUS: OnceLock<ExceptionFilter> = OnceLock::new();
+
+    pub unsafe extern "system" fn on_exception(info: *const c_void) -> i32 {
+        write_crash_dump("unhandled native exception", false);
+
+        match PREVIOUS.get().copied().flatten() {
+            Some(previous) => previous(info),
+            None => EXCEPTION_CONTINUE_SEARCH,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn install_exception_handler() {}
+
+#[cfg(not(windows))]
+pub fn uninstall_exception_handler() {}
@@ -0,0 +1,205 @@
+use regex::Regex;
+use std::{fmt::Display, str::FromStr};
+
+/// a value crossing the native boundary through a push or get hook
+#[derive(Clone, Debug)]
+pub enum DataValue {
+    String(String),
+    Integer(i64),
+    Float(f32),
+    Bool(bool),
+}
+
+/// what a data breakpoint stops on, written as `value`, `=value`, `min..max` or `/regex/`
+#[derive(Clone, Debug)]
+pub enum DataPredicate {
+    /// numbers and bools are compared by value so `=1.0` matches a 1, anything else as it shows up in the trace
+    Equals(String),
+    /// inclusive, only integers and floats can be in it
+    Range(f64, f64),
+    Regex(Regex),
+}
+
+impl Display for DataValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataValue::String(value) => f.write_str(value),
+            DataValue::Integer(value) => value.fmt(f),
+            DataValue::Float(value) => value.fmt(f),
+            DataValue::Bool(value) => value.fmt(f),
+        }
+    }
+}
+
+impl Display for DataPredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataPredicate::Equals(value) => f.write_fmt(format_args!("={value}")),
+            DataPredicate::Range(min, max) => f.write_fmt(format_args!("{min}..{max}")),
+            DataPredicate::Regex(regex) => f.write_fmt(format_args!("/{regex}/")),
+        }
+    }
+}
+
+impl FromStr for DataPredicate {
+    type Err = String;
+
+    fn from_str(predicate: &str) -> Result<Self, Self::Err> {
+        if let Some(regex) = predicate
+            .strip_prefix('/')
+            .and_then(|regex| regex.strip_suffix('/'))
+        {
+            return Regex::new(regex)
+                .map(DataPredicate::Regex)
+                .map_err(|err| format!("{predicate} is not a valid regex: {err}"));
+        }
+
+        if let Some(value) = predicate.strip_prefix('=') {
+            return Ok(DataPredicate::Equals(value.to_string()));
+        }
+
+        if let Some((min, max)) = predicate.split_once("..") {
+            return match (min.parse(), max.parse()) {
+                (Ok(min), Ok(max)) if min <= max => Ok(DataPredicate::Range(min, max)),
+                _ => Err(format!(
+                    "{predicate} is not a valid range; expected min..max"
+                )),
+            };
+        }
+
+        Ok(DataPredicate::Equals(predicate.to_string()))
+    }
+}
+
+impl PartialEq for DataPredicate {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (DataPredicate::Equals(a), DataPredicate::Equals(b)) => a == b,
+            (DataPredicate::Range(a_min, a_max), DataPredicate::Range(b_min, b_max)) => {
+                a_min == b_min && a_max == b_max
+            }
+            (DataPredicate::Regex(a), DataPredicate::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl DataPredicate {
+    pub fn matches(&self, value: &DataValue) -> bool {
+        match (self, value) {
+            (DataPredicate::Equals(expected), DataValue::String(value)) => expected == value,
+            (DataPredicate::Equals(expected), DataValue::Integer(value)) => {
+                match expected.parse::<i64>() {
+                    Ok(expected) => expected == *value,
+                    Err(_) => expected
+                        .parse::<f64>()
+                        .is_ok_and(|expected| expected == *value as f64),
+                }
+            }
+            // compared as f32 since that is all the vm keeps, `=0.1` has to match the f32 0.1
+            (DataPredicate::Equals(expected), DataValue::Float(value)) => expected
+                .parse::<f32>()
+                .is_ok_and(|expected| expected == *value),
+            (DataPredicate::Equals(expected), DataValue::Bool(value)) => expected
+                .parse::<bool>()
+                .is_ok_and(|expected| expected == *value),
+            (DataPredicate::Range(min, max), DataValue::Integer(value)) => {
+                (*min..=*max).contains(&(*value as f64))
+            }
+            (DataPredicate::Range(min, max), DataValue::Float(value)) => {
+                (*min..=*max).contains(&(*value as f64))
+            }
+            (DataPredicate::Range(..), _) => false,
+            (DataPredicate::Regex(regex), DataValue::String(value)) => regex.is_match(value),
+            (DataPredicate::Regex(regex), value) => regex.is_match(&value.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predicate(predicate: &str) -> DataPredicate {
+        predicate.parse().expect("the test predicate should parse")
+    }
+
+    #[test]
+    fn parses_every_kind() {
+        assert!(
+            matches!(predicate("mp_titan"), DataPredicate::Equals(value) if value == "mp_titan")
+        );
+        assert!(matches!(predicate("=1..2"), DataPredicate::Equals(value) if value == "1..2"));
+        assert!(
+            matches!(predicate("-1.5..10"), DataPredicate::Range(min, max) if min == -1.5 && max == 10.0)
+        );
+        assert!(
+            matches!(predicate("/^weapon_/"), DataPredicate::Regex(regex) if regex.as_str() == "^weapon_")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_predicates() {
+        assert!("5..1".parse::<DataPredicate>().is_err());
+        assert!("a..b".parse::<DataPredicate>().is_err());
+        assert!("1..".parse::<DataPredicate>().is_err());
+        assert!("/(/".parse::<DataPredicate>().is_err());
+    }
+
+    #[test]
+    fn equals_compares_numbers_by_value() {
+        assert!(predicate("=1.0").matches(&DataValue::Float(1.0)));
+        assert!(predicate("=1.0").matches(&DataValue::Integer(1)));
+        assert!(predicate("=1").matches(&DataValue::Float(1.0)));
+        assert!(predicate("=0.1").matches(&DataValue::Float(0.1)));
+        assert!(predicate("=-3").matches(&DataValue::Integer(-3)));
+        assert!(!predicate("=1").matches(&DataValue::Integer(10)));
+        assert!(!predicate("=one").matches(&DataValue::Integer(1)));
+        assert!(predicate("=true").matches(&DataValue::Bool(true)));
+        assert!(!predicate("=1").matches(&DataValue::Bool(true)));
+    }
+
+    #[test]
+    fn equals_compares_strings_exactly() {
+        assert!(predicate("=mp_titan").matches(&DataValue::String("mp_titan".to_string())));
+        assert!(!predicate("=mp_titan").matches(&DataValue::String("mp_titan_atlas".to_string())));
+        assert!(predicate("=1").matches(&DataValue::String("1".to_string())));
+        assert!(!predicate("=1.0").matches(&DataValue::String("1".to_string())));
+    }
+
+    #[test]
+    fn range_is_inclusive_and_only_numeric() {
+        let range = predicate("9000..10000");
+        assert!(range.matches(&DataValue::Integer(9000)));
+        assert!(range.matches(&DataValue::Float(10000.0)));
+        assert!(!range.matches(&DataValue::Integer(10001)));
+        assert!(!range.matches(&DataValue::Float(8999.5)));
+        assert!(!range.matches(&DataValue::String("9500".to_string())));
+        assert!(!range.matches(&DataValue::Bool(true)));
+    }
+
+    #[test]
+    fn regex_matches_the_value_as_shown() {
+        let regex = predicate("/^mp_weapon_/");
+        assert!(regex.matches(&DataValue::String("mp_weapon_car".to_string())));
+        assert!(!regex.matches(&DataValue::String("melee_pilot_emptyhanded".to_string())));
+        assert!(predicate("/^4[0-9]$/").matches(&DataValue::Integer(42)));
+        assert!(predicate("/^true$/").matches(&DataValue::Bool(true)));
+    }
+
+    #[test]
+    fn equal_predicates() {
+        assert!(predicate("/^mp_/") == predicate("/^mp_/"));
+        assert!(predicate("/^mp_/") != predicate("/^mp/"));
+        assert!(predicate("mp_titan") == predicate("=mp_titan"));
+        assert!(predicate("1..2") == predicate("1.0..2"));
+        assert!(predicate("=1..2") != predicate("1..2"));
+    }
+
+    #[test]
+    fn shown_predicates_parse_back() {
+        for source in ["mp_titan", "=1..2", "-1.5..10", "/^weapon_/"] {
+            assert!(predicate(&predicate(source).to_string()) == predicate(source));
+        }
+    }
+}
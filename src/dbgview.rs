@@ -6,11 +6,27 @@ use egui_winit::winit::{
     event_loop::EventLoopBuilder, platform::windows::EventLoopBuilderExtWindows,
 };
 use rrplug::prelude::ScriptContext;
-use std::sync::mpsc::Receiver;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::Path,
+    sync::atomic::Ordering,
+    time::Duration,
+};
 
-use crate::{exports::PLUGIN, stacktrace::StackTrace, VmSpecific};
+use crate::{
+    calltree::{CallNode, CallTree},
+    config::{reload_config, UiConfig},
+    databreak::DataPredicate,
+    debug::{PauseReason, RunState, ScriptBreakKind},
+    events::MAX_EVENTS_PER_FRAME,
+    exports::PLUGIN,
+    hooks::DetourSet,
+    session::{save_session_if_changed, Session},
+    stacktrace::StackTrace,
+    VmSpecific, ALL_CONTEXTS,
+};
 
-pub fn init_window(recv: Receiver<(ScriptContext, StackTrace)>) {
+pub fn init_window(config: UiConfig) {
     let func = |event_loop_builder: &mut EventLoopBuilder<RequestRepaintEvent>| {
         event_loop_builder.with_any_thread(true);
     };
@@ -22,75 +38,563 @@ pub fn init_window(recv: Receiver<(ScriptContext, StackTrace)>) {
         icon_data: None,
         resizable: true,
         follow_system_theme: false,
-        run_and_return: false,
+        // returning instead of exiting the process lets the window close on shutdown
+        run_and_return: true,
         event_loop_builder,
         centered: true,
-        initial_window_size: Some(eframe::epaint::Vec2::new(500., 400.)),
+        initial_window_size: Some(eframe::epaint::Vec2::new(config.width, config.height)),
 
         ..Default::default()
     };
 
+    let title = config.title.clone();
     eframe::run_native(
-        "Debugger :3",
+        &title,
         options,
-        Box::new(move |_cc| Box::new(Window::new(recv))),
+        Box::new(move |_cc| {
+            PLUGIN.wait().attached.store(true, Ordering::Relaxed);
+            let context = PLUGIN.wait().session.lock().context;
+            Box::new(Window::new(
+                context.unwrap_or_else(|| config.default_context()),
+            ))
+        }),
     );
+
+    // closed by the user or on shutdown, either way nobody is looking anymore
+    PLUGIN.wait().attached.store(false, Ordering::Relaxed);
 }
+
+#[derive(PartialEq, Eq)]
+enum LogView {
+    Flat,
+    Tree,
+}
+
 struct Window {
     context: ScriptContext,
-    recv: Receiver<(ScriptContext, StackTrace)>,
-    sqlog: VmSpecific<Vec<StackTrace>>,
+    sqlog: VmSpecific<VecDeque<StackTrace>>,
+    calltree: VmSpecific<CallTree>,
+    view: LogView,
+    new_breakpoint: String,
+    new_script_breakpoint: String,
+    script_breakpoint_error: Option<String>,
+    new_data_breakpoint: String,
+    data_breakpoint_error: Option<String>,
+    run_to_function: String,
+    new_skip: String,
+    config_error: Option<String>,
+    new_watch: String,
+    new_hidden_function: String,
+    preset_name: String,
+    session_file: String,
+    session_error: Option<String>,
+    watches: VmSpecific<BTreeMap<String, String>>,
 }
 
 impl Window {
-    fn new(recv: Receiver<(ScriptContext, StackTrace)>) -> Self {
+    fn new(context: ScriptContext) -> Self {
         Self {
-            context: ScriptContext::UI,
-            recv,
+            context,
             sqlog: VmSpecific::new(),
+            calltree: VmSpecific::new(),
+            view: LogView::Flat,
+            new_breakpoint: String::new(),
+            new_script_breakpoint: String::new(),
+            script_breakpoint_error: None,
+            new_data_breakpoint: String::new(),
+            data_breakpoint_error: None,
+            run_to_function: String::new(),
+            new_skip: String::new(),
+            config_error: None,
+            new_watch: String::new(),
+            new_hidden_function: String::new(),
+            preset_name: String::new(),
+            session_file: String::new(),
+            session_error: None,
+            watches: VmSpecific::new(),
         }
     }
 }
 
+fn show_call_node(ui: &mut egui::Ui, node: &CallNode, id: egui::Id) {
+    let text = format!(
+        "{}({}) x{} {:?}",
+        node.name, node.last_args, node.calls, node.total
+    );
+
+    if node.children.is_empty() {
+        ui.label(text);
+        return;
+    }
+
+    egui::CollapsingHeader::new(text)
+        .id_source(id)
+        .show(ui, |ui| {
+            for (i, child) in node.children.iter().enumerate() {
+                show_call_node(ui, child, id.with(i));
+            }
+        });
+}
+
+fn run_state_color(run_state: &RunState) -> egui::Color32 {
+    match run_state {
+        RunState::Running => egui::Color32::GREEN,
+        RunState::Paused { .. } => egui::Color32::RED,
+        RunState::PauseRequested(_) | RunState::Stepping { .. } => egui::Color32::YELLOW,
+    }
+}
+
 impl eframe::App for Window {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        while let Ok((context, log)) = self.recv.try_recv() {
-            self.sqlog.get_mut(context).push(log)
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let plugin = PLUGIN.wait();
+        if plugin.is_shutting_down() {
+            plugin.events.discard();
+            frame.close();
+            return;
         }
+        // keep polling even without input so new traces and shutdowns are noticed
+        ctx.request_repaint_after(Duration::from_millis(100));
+
+        // everything queued since the last frame is handled in one batch
+        let limit = plugin.recording.limit();
+        let filters = plugin.settings.filters.lock().clone();
+        plugin.events.drain(MAX_EVENTS_PER_FRAME, |context, log| {
+            if !filters.allows(&log) {
+                return;
+            }
+            plugin.recording.record(context, &log);
+            if let StackTrace::Watch { name, value } = &log {
+                self.watches
+                    .get_mut(context)
+                    .insert(name.clone(), value.to_string());
+            }
+            if let StackTrace::VmCreated(_) = log {
+                self.sqlog.get_mut(context).clear();
+                self.calltree.get_mut(context).clear();
+                self.watches.get_mut(context).clear();
+            }
+            self.calltree.get_mut(context).record(&log);
+
+            let sqlog = self.sqlog.get_mut(context);
+            while sqlog.len() >= limit {
+                sqlog.pop_front();
+            }
+            sqlog.push_back(log)
+        });
+        save_session_if_changed(plugin);
+
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for context in ALL_CONTEXTS {
+                    let run_state = plugin.debug_info.get(context).state();
+                    ui.colored_label(
+                        run_state_color(&run_state),
+                        format!("{context:?}: {run_state}"),
+                    );
+                    ui.separator();
+                }
+            });
+        });
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            ComboBox::from_label("SQVM context")
-                .selected_text(format!("{:?}", self.context))
-                .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.context, ScriptContext::SERVER, "SERVER");
-                    ui.selectable_value(&mut self.context, ScriptContext::CLIENT, "CLIENT");
-                    ui.selectable_value(&mut self.context, ScriptContext::UI, "UI");
-                });
+            ui.horizontal(|ui| {
+                ComboBox::from_label("SQVM context")
+                    .selected_text(format!("{:?}", self.context))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.context, ScriptContext::SERVER, "SERVER");
+                        ui.selectable_value(&mut self.context, ScriptContext::CLIENT, "CLIENT");
+                        ui.selectable_value(&mut self.context, ScriptContext::UI, "UI");
+                    });
+
+                for context in ALL_CONTEXTS {
+                    let state = plugin.debug_info.get(context);
+                    let (text, color) = if state.alive.load(Ordering::Relaxed) {
+                        ("alive", egui::Color32::GREEN)
+                    } else {
+                        ("dead", egui::Color32::GRAY)
+                    };
+                    ui.colored_label(
+                        color,
+                        format!(
+                            "{context:?} #{} {text}",
+                            state.generation.load(Ordering::Relaxed)
+                        ),
+                    );
+                }
+
+                if ui.button("reload config").clicked() {
+                    self.config_error = reload_config(plugin).err().map(|err| err.to_string());
+                }
+            });
+            if let Some(err) = &self.config_error {
+                ui.colored_label(egui::Color32::RED, err);
+            }
+
+            {
+                let mut session = plugin.session.lock();
+                if session.context != Some(self.context) {
+                    session.context = Some(self.context);
+                    session.dirty = true;
+                }
+            }
+
+            let state = plugin.debug_info.get(self.context);
+            ui.collapsing("Hook routing", |ui| {
+                for context in ALL_CONTEXTS {
+                    let state = plugin.debug_info.get(context);
+                    let set = DetourSet::for_context(context);
+                    // counted by the hooks themselves so a vm ending up in the wrong dll's detours shows here
+                    let served = [DetourSet::Client, DetourSet::Server]
+                        .into_iter()
+                        .map(|served_by| {
+                            format!(
+                                "{} through {served_by:?}",
+                                state.hook_calls[served_by.index()].load(Ordering::Relaxed)
+                            )
+                        })
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    ui.label(format!(
+                        "{context:?}: {set:?} detours ({}), hooks {}, calls {served}",
+                        if set.is_installed() {
+                            "installed"
+                        } else {
+                            "missing"
+                        },
+                        if state.hooks_enabled.load(Ordering::Relaxed) {
+                            "on"
+                        } else {
+                            "off"
+                        },
+                    ));
+                }
+            });
+
+            ui.label(format!("events: {}", plugin.events.counters()));
+
+            let diagnostics = plugin.diagnostics.snapshot();
+            ui.collapsing(format!("Diagnostics ({})", diagnostics.len()), |ui| {
+                for (message, count) in &diagnostics {
+                    ui.label(format!("{message} (x{count})"));
+                }
+
+                if ui.button("clear").clicked() {
+                    plugin.diagnostics.clear();
+                }
+            });
 
             ui.horizontal(|ui| {
                 if ui.button("Pause").clicked() {
-                    (*PLUGIN.wait().debug_info.get(self.context).paused.lock()) = true;
+                    state.pause(PauseReason::Requested);
+                }
+
+                if ui
+                    .button("Run")
+                    .on_hover_text("lets the vm run freely until something stops it")
+                    .clicked()
+                {
+                    state.unpause();
+                }
+
+                if ui
+                    .button("Step")
+                    .on_hover_text("lets the vm run until the next hook")
+                    .clicked()
+                {
+                    state.resume();
+                }
+
+                if ui
+                    .button("break on next call")
+                    .on_hover_text("only native code calling into scripts through sq_call stops")
+                    .clicked()
+                {
+                    state.break_on_next_call();
                 }
 
-                if ui.button("Unpause").clicked() {
-                    (*PLUGIN.wait().debug_info.get(self.context).paused.lock()) = false;
+                ui.text_edit_singleline(&mut self.run_to_function);
+                if ui
+                    .button("run to")
+                    .on_hover_text("stops once native code calls the function through sq_call")
+                    .clicked()
+                    && !self.run_to_function.is_empty()
+                {
+                    state.run_to(self.run_to_function.clone());
+                }
+
+                ui.separator();
+
+                if ui.button("Pause all").clicked() {
+                    plugin.pause_all(PauseReason::Requested);
                 }
 
-                if ui.button("continue").clicked() {
-                    _ = PLUGIN
-                        .wait()
-                        .debug_info
-                        .get(self.context)
-                        .unpause_breaker
-                        .lock()
-                        .send(());
+                if ui.button("Run all").clicked() {
+                    plugin.unpause_all();
+                }
+
+                if ui.button("Step all").clicked() {
+                    plugin.step_all();
+                }
+            });
+
+            ui.collapsing("Breakpoints", |ui| {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.new_breakpoint);
+                    if ui.button("add").clicked() && !self.new_breakpoint.is_empty() {
+                        state.add_breakpoint(std::mem::take(&mut self.new_breakpoint));
+                    }
+                });
+
+                let breakpoints = state.breakpoints.lock().clone();
+                for breakpoint in breakpoints {
+                    ui.horizontal(|ui| {
+                        ui.label(&breakpoint);
+                        if ui.button("remove").clicked() {
+                            state.remove_breakpoint(&breakpoint);
+                        }
+                    });
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.new_script_breakpoint);
+                    for (label, kind) in [
+                        ("break on load", ScriptBreakKind::Load),
+                        ("break on first call", ScriptBreakKind::FirstCall),
+                    ] {
+                        if ui.button(label).clicked() && !self.new_script_breakpoint.is_empty() {
+                            self.script_breakpoint_error = state
+                                .add_script_breakpoint(&self.new_script_breakpoint, kind)
+                                .err()
+                                .map(|err| err.to_string());
+                            if self.script_breakpoint_error.is_none() {
+                                self.new_script_breakpoint.clear();
+                            }
+                        }
+                    }
+                });
+                if let Some(err) = &self.script_breakpoint_error {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+
+                let script_breakpoints = state
+                    .script_breakpoints
+                    .lock()
+                    .iter()
+                    .map(|breakpoint| (breakpoint.pattern.as_str().to_string(), breakpoint.kind))
+                    .collect::<Vec<(String, ScriptBreakKind)>>();
+                for (pattern, kind) in script_breakpoints {
+                    ui.horizontal(|ui| {
+                        match kind {
+                            ScriptBreakKind::Load => ui.label(format!("load {pattern}")),
+                            ScriptBreakKind::FirstCall => ui.label(format!("first call {pattern}")),
+                        };
+                        if ui.button("remove").clicked() {
+                            state.remove_script_breakpoint(&pattern, kind);
+                        }
+                    });
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.new_data_breakpoint)
+                        .on_hover_text("value, =value, min..max or /regex/");
+                    if ui.button("break on value").clicked() && !self.new_data_breakpoint.is_empty()
+                    {
+                        match self.new_data_breakpoint.parse::<DataPredicate>() {
+                            Ok(predicate) => {
+                                state.add_data_breakpoint(predicate);
+                                self.new_data_breakpoint.clear();
+                                self.data_breakpoint_error = None;
+                            }
+                            Err(err) => self.data_breakpoint_error = Some(err),
+                        }
+                    }
+                });
+                if let Some(err) = &self.data_breakpoint_error {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+
+                let data_breakpoints = state.data_breakpoints.lock().clone();
+                for predicate in data_breakpoints {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("value {predicate}"));
+                        if ui.button("remove").clicked() {
+                            state.remove_data_breakpoint(&predicate);
+                        }
+                    });
+                }
+            });
+
+            ui.collapsing("Watches", |ui| {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.new_watch);
+                    if ui.button("pin").clicked() && !self.new_watch.is_empty() {
+                        let mut session = plugin.session.lock();
+                        session.watches.push(std::mem::take(&mut self.new_watch));
+                        session.dirty = true;
+                    }
+                });
+
+                let values = self.watches.get(self.context);
+                let pinned = plugin.session.lock().watches.clone();
+                for (i, name) in pinned.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let value = values.get(name).map(String::as_str).unwrap_or("<unset>");
+                        ui.strong(format!("{name} = {value}"));
+                        if ui.button("unpin").clicked() {
+                            let mut session = plugin.session.lock();
+                            session.watches.remove(i);
+                            session.dirty = true;
+                        }
+                    });
+                }
+
+                for (name, value) in values.iter().filter(|(name, _)| !pinned.contains(name)) {
+                    ui.label(format!("{name} = {value}"));
+                }
+            });
+
+            ui.collapsing("Skip list", |ui| {
+                // edited on a copy so the vm doesn't wait on the lock while the panel is drawn
+                let previous = state.skip_list.lock().clone();
+                let mut skip_list = previous.clone();
+                ui.checkbox(&mut skip_list.hide, "hide skipped code from the trace");
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.new_skip);
+                    if ui.button("skip function").clicked() && !self.new_skip.is_empty() {
+                        skip_list
+                            .functions
+                            .insert(std::mem::take(&mut self.new_skip));
+                    }
+                    if ui.button("skip file").clicked() && !self.new_skip.is_empty() {
+                        skip_list.files.push(std::mem::take(&mut self.new_skip));
+                    }
+                });
+
+                for function in &previous.functions {
+                    ui.horizontal(|ui| {
+                        ui.label(function);
+                        if ui.button("remove").clicked() {
+                            skip_list.functions.remove(function);
+                        }
+                    });
+                }
+                for file in &previous.files {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("file {file}"));
+                        if ui.button("remove").clicked() {
+                            skip_list.files.retain(|skipped| skipped != file);
+                        }
+                    });
+                }
+
+                if skip_list != previous {
+                    state.set_skip_list(skip_list);
                 }
             });
 
-            ui.label("Squirrel Functions log");
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                for trace in self.sqlog.get(self.context) {
-                    ui.label(format!("{trace}"));
+            ui.collapsing("Filters", |ui| {
+                // edited on a copy like the skip list, the drain and the session only lock them for a moment
+                let previous = plugin.settings.filters.lock().clone();
+                let mut filters = previous.clone();
+                let presets = plugin.session.lock().filter_presets.clone();
+                ui.checkbox(&mut filters.hide_values, "hide pushed and fetched values");
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.new_hidden_function);
+                    if ui.button("hide function").clicked() && !self.new_hidden_function.is_empty()
+                    {
+                        filters
+                            .hide_functions
+                            .insert(std::mem::take(&mut self.new_hidden_function));
+                    }
+                });
+                for function in &previous.hide_functions {
+                    ui.horizontal(|ui| {
+                        ui.label(function);
+                        if ui.button("show").clicked() {
+                            filters.hide_functions.remove(function);
+                        }
+                    });
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.preset_name);
+                    if ui.button("save preset").clicked() && !self.preset_name.is_empty() {
+                        let mut session = plugin.session.lock();
+                        session
+                            .filter_presets
+                            .insert(std::mem::take(&mut self.preset_name), filters.clone());
+                        session.dirty = true;
+                    }
+                });
+                for (name, preset) in &presets {
+                    ui.horizontal(|ui| {
+                        ui.label(name);
+                        if ui.button("apply").clicked() {
+                            filters = preset.clone();
+                        }
+                        if ui.button("delete").clicked() {
+                            let mut session = plugin.session.lock();
+                            session.filter_presets.remove(name);
+                            session.dirty = true;
+                        }
+                    });
+                }
+
+                // the active filters are part of the session too
+                if filters != previous {
+                    *plugin.settings.filters.lock() = filters;
+                    plugin.session.lock().dirty = true;
+                }
+            });
+
+            ui.collapsing("Session", |ui| {
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.session_file);
+                    if ui.button("export").clicked() {
+                        self.session_error = Session::capture(plugin)
+                            .save(Path::new(&self.session_file))
+                            .err()
+                            .map(|err| err.to_string());
+                    }
+                    if ui.button("import").clicked() {
+                        match Session::load(Path::new(&self.session_file)) {
+                            Ok(session) => {
+                                session.apply(plugin);
+                                if let Some(context) = plugin.session.lock().context {
+                                    self.context = context;
+                                }
+                                self.session_error = None;
+                            }
+                            Err(err) => self.session_error = Some(err.to_string()),
+                        }
+                    }
+                });
+                if let Some(err) = &self.session_error {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Squirrel Functions log");
+                ui.selectable_value(&mut self.view, LogView::Flat, "flat");
+                ui.selectable_value(&mut self.view, LogView::Tree, "call tree");
+            });
+            egui::ScrollArea::vertical().show(ui, |ui| match self.view {
+                LogView::Flat => {
+                    for trace in self.sqlog.get(self.context) {
+                        ui.label(format!("{trace}"));
+                    }
+                }
+                LogView::Tree => {
+                    let id = egui::Id::new(("calltree", self.context as i32));
+                    for (i, node) in self.calltree.get(self.context).roots().iter().enumerate() {
+                        show_call_node(ui, node, id.with(i));
+                    }
                 }
             });
         });
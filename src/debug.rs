@@ -1,19 +1,1210 @@
-use parking_lot::Mutex;
-use std::sync::mpsc::{self, Receiver, SyncSender};
+use glob::{Pattern, PatternError};
+use parking_lot::{Condvar, Mutex};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::SkipList,
+    databreak::{DataPredicate, DataValue},
+    stacktrace::FunctionName,
+};
+
+/// why a vm stopped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PauseReason {
+    /// paused from the ui, a command or the remote client
+    Requested,
+    Breakpoint(String),
+    Error(String),
+    /// `DebugBreak()` was called by a script
+    Script,
+    Step,
+    /// the function has been running for longer than the watchdog threshold
+    Watchdog(String),
+    /// the first call after "break on next call"
+    NextCall(String),
+    /// reached the function of a "run to"
+    RunTo(String),
+    /// a script matching a load breakpoint is about to be compiled
+    ScriptLoad(String),
+    /// the first call into a function of a script matching a first call breakpoint
+    FirstCall {
+        function: String,
+        file: String,
+    },
+    /// a value pushed or fetched by `hook` at `position` on the stack matched a data breakpoint
+    Value {
+        hook: &'static str,
+        position: i64,
+        value: String,
+        predicate: String,
+    },
+}
+
+/// where a vm stopped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub hook: &'static str,
+    pub depth: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptBreakKind {
+    /// stops when the script is compiled
+    Load,
+    /// stops the first time any function of the script is called
+    FirstCall,
+}
+
+/// a breakpoint on every script whose path matches a glob
+pub struct ScriptBreakpoint {
+    pub pattern: Pattern,
+    pub kind: ScriptBreakKind,
+}
+
+/// what the config file added, so a reload can take exactly those back out
+#[derive(Default)]
+pub struct ConfiguredBreakpoints {
+    pub functions: Vec<String>,
+    pub scripts: Vec<(String, ScriptBreakKind)>,
+    pub values: Vec<DataPredicate>,
+}
+
+/// a `sq_call` the vm is currently inside of, only tracked while the watchdog is on
+pub struct CallFrame {
+    pub function: FunctionName,
+    pub entered: Instant,
+    /// the watchdog only reports a frame once
+    pub reported: bool,
+}
+
+/// leaves its `CallFrame` once dropped so a panic while the call is traced can't leave it behind
+#[must_use]
+pub struct EnteredCall<'a> {
+    state: &'a DebugState,
+}
+
+impl Drop for EnteredCall<'_> {
+    fn drop(&mut self) {
+        self.state.call_stack.lock().pop();
+    }
+}
+
+/// a `sq_call` the hooks are tracing, everything it added to the vm is taken back once dropped
+///
+/// lives outside of `guarded` so neither a panic before nor after the call can leave the depths behind
+#[must_use]
+pub struct TracedCall<'a> {
+    state: &'a DebugState,
+    depth: usize,
+    skipped: bool,
+    /// only held on to so it's left together with the call
+    _frame: Option<EnteredCall<'a>>,
+    started: Instant,
+    /// `DebugState::paused_time` when the clock started
+    paused_before: Duration,
+}
+
+impl TracedCall<'_> {
+    /// how many calls the vm was already inside of
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// nothing stops until a skipped call returned
+    pub fn enter_skipped(&mut self, function: Option<&str>, file: Option<&str>) {
+        if !self.skipped {
+            self.skipped = self.state.enter_skipped(function, file);
+        }
+    }
+
+    pub fn enter_call(&mut self, function: FunctionName) {
+        self._frame = Some(self.state.enter_call(function));
+    }
+
+    /// starts timing the call over, a pause right at the call isn't part of it
+    pub fn start_timing(&mut self) {
+        self.started = Instant::now();
+        self.paused_before = self.state.paused_time();
+    }
+
+    /// how long the call ran without the time the vm sat paused inside of it
+    pub fn elapsed(&self) -> Duration {
+        let paused = self.state.paused_time().saturating_sub(self.paused_before);
+        self.started.elapsed().saturating_sub(paused)
+    }
+}
+
+impl Drop for TracedCall<'_> {
+    fn drop(&mut self) {
+        // the vm may have been recreated in between and started over at 0
+        _ = self
+            .state
+            .call_depth
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |depth| {
+                depth.checked_sub(1)
+            });
+        if self.skipped {
+            self.state.leave_skipped();
+        }
+    }
+}
+
+/// how a stop at a checkpoint ended
+pub struct Stop {
+    pub reason: PauseReason,
+    /// set if the pause ran into the maximum pause duration instead of being resumed
+    pub timed_out: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepMode {
+    /// stop again at whatever hook runs next
+    NextHook,
+}
+
+/// `Running` -> `PauseRequested` -> `Paused` once the vm reaches a hook -> `Stepping` -> `Paused` or back to `Running`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    PauseRequested(PauseReason),
+    Paused {
+        reason: PauseReason,
+        location: Location,
+    },
+    Stepping {
+        mode: StepMode,
+    },
+}
 
 pub struct DebugState {
-    pub paused: Mutex<bool>,
-    pub unpause_waiter: Mutex<Receiver<()>>,
-    pub unpause_breaker: Mutex<SyncSender<()>>,
+    pub alive: AtomicBool,
+    /// bumped every time the vm is created again, mostly on map changes
+    pub generation: AtomicUsize,
+    /// when off the hooks of this vm only call the original function
+    pub hooks_enabled: AtomicBool,
+    /// how many hooked calls the detours of each dll served for this vm, see `DetourSet::index`
+    pub hook_calls: [AtomicUsize; 2],
+    pub run_state: Mutex<RunState>,
+    /// woken whenever `run_state` leaves `Paused`
+    run_state_changed: Condvar,
+    /// mirrors `run_state != Running` so idle hooks don't have to lock it
+    stop_pending: AtomicBool,
+    /// set while the vm thread sits in `checkpoint`
+    blocked: AtomicBool,
+    /// bumped every time the vm thread leaves `checkpoint`, a step can block again before `blocked` is seen cleared
+    releases: AtomicUsize,
+    /// a paused vm runs again after this long, 0 waits forever
+    pub max_pause_ms: AtomicUsize,
+    /// how long the vm sat paused in total, in nanoseconds
+    paused_nanos: AtomicU64,
+    pub call_depth: AtomicUsize,
+    pub call_stack: Mutex<Vec<CallFrame>>,
+    pub breakpoints: Mutex<HashSet<String>>,
+    /// removed once they are hit, never saved in the session
+    pub temporary_breakpoints: Mutex<HashSet<String>>,
+    break_on_next_call: AtomicBool,
+    pub script_breakpoints: Mutex<Vec<ScriptBreakpoint>>,
+    /// scripts that already had their first call, forgotten when the vm is recreated
+    first_calls: Mutex<HashSet<String>>,
+    has_first_call_breakpoints: AtomicBool,
+    has_load_breakpoints: AtomicBool,
+    /// checked against every value the push and get hooks see
+    pub data_breakpoints: Mutex<Vec<DataPredicate>>,
+    has_data_breakpoints: AtomicBool,
+    configured: Mutex<ConfiguredBreakpoints>,
+    /// set while any of the above could stop a call so idle hooks can skip resolving names
+    has_breakpoints: AtomicBool,
+    pub skip_list: Mutex<SkipList>,
+    has_skips: AtomicBool,
+    /// how many skipped calls the vm is inside of, nothing stops while it isn't 0
+    skip_depth: AtomicUsize,
+    pub breakpoints_changed: AtomicBool,
 }
 
 impl Default for DebugState {
     fn default() -> Self {
-        let (send, recv) = mpsc::sync_channel(1);
         Self {
-            paused: false.into(),
-            unpause_waiter: recv.into(),
-            unpause_breaker: send.into(),
+            alive: AtomicBool::new(false),
+            generation: AtomicUsize::new(0),
+            hooks_enabled: AtomicBool::new(true),
+            hook_calls: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            run_state: Mutex::new(RunState::Running),
+            run_state_changed: Condvar::new(),
+            stop_pending: AtomicBool::new(false),
+            blocked: AtomicBool::new(false),
+            releases: AtomicUsize::new(0),
+            max_pause_ms: AtomicUsize::new(0),
+            paused_nanos: AtomicU64::new(0),
+            call_depth: AtomicUsize::new(0),
+            call_stack: Mutex::new(Vec::new()),
+            breakpoints: Mutex::new(HashSet::new()),
+            temporary_breakpoints: Mutex::new(HashSet::new()),
+            break_on_next_call: AtomicBool::new(false),
+            script_breakpoints: Mutex::new(Vec::new()),
+            first_calls: Mutex::new(HashSet::new()),
+            has_first_call_breakpoints: AtomicBool::new(false),
+            has_load_breakpoints: AtomicBool::new(false),
+            data_breakpoints: Mutex::new(Vec::new()),
+            has_data_breakpoints: AtomicBool::new(false),
+            configured: Mutex::new(ConfiguredBreakpoints::default()),
+            has_breakpoints: AtomicBool::new(false),
+            skip_list: Mutex::new(SkipList::default()),
+            has_skips: AtomicBool::new(false),
+            skip_depth: AtomicUsize::new(0),
+            breakpoints_changed: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Display for PauseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PauseReason::Requested => f.write_str("pause requested"),
+            PauseReason::Breakpoint(function) => {
+                f.write_fmt(format_args!("breakpoint on {function}"))
+            }
+            PauseReason::Error(error) => f.write_fmt(format_args!("error: {error}")),
+            PauseReason::Script => f.write_str("DebugBreak"),
+            PauseReason::Step => f.write_str("step"),
+            PauseReason::Watchdog(function) => {
+                f.write_fmt(format_args!("{function} ran past the watchdog threshold"))
+            }
+            PauseReason::NextCall(function) => f.write_fmt(format_args!("next call {function}")),
+            PauseReason::RunTo(function) => f.write_fmt(format_args!("ran to {function}")),
+            PauseReason::ScriptLoad(file) => f.write_fmt(format_args!("loading {file}")),
+            PauseReason::FirstCall { function, file } => {
+                f.write_fmt(format_args!("first call into {file} with {function}"))
+            }
+            PauseReason::Value {
+                hook,
+                position,
+                value,
+                predicate,
+            } => f.write_fmt(format_args!(
+                "{hook} at stack position {position} saw {value} matching {predicate}"
+            )),
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{} at depth {}", self.hook, self.depth))
+    }
+}
+
+impl Display for RunState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunState::Running => f.write_str("running"),
+            RunState::PauseRequested(reason) => {
+                f.write_fmt(format_args!("pausing at the next hook ({reason})"))
+            }
+            RunState::Paused { reason, location } => {
+                f.write_fmt(format_args!("paused in {location} ({reason})"))
+            }
+            RunState::Stepping { mode } => f.write_fmt(format_args!("stepping ({mode:?})")),
+        }
+    }
+}
+
+impl RunState {
+    /// the first reason wins if a pause was already requested, a paused vm stays where it is
+    pub fn pause(&mut self, reason: PauseReason) {
+        match self {
+            RunState::Running | RunState::Stepping { .. } => {
+                *self = RunState::PauseRequested(reason)
+            }
+            RunState::PauseRequested(_) | RunState::Paused { .. } => {}
+        }
+    }
+
+    pub fn unpause(&mut self) {
+        *self = RunState::Running;
+    }
+
+    /// only a paused vm can step, returns whether it did
+    pub fn step(&mut self, mode: StepMode) -> bool {
+        match self {
+            RunState::Paused { .. } => {
+                *self = RunState::Stepping { mode };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// called by the vm when it reaches a hook, returns why it has to stop there
+    pub fn reach(&mut self, location: Location) -> Option<PauseReason> {
+        let reason = match self {
+            RunState::Running | RunState::Paused { .. } => return None,
+            RunState::PauseRequested(reason) => reason.clone(),
+            RunState::Stepping {
+                mode: StepMode::NextHook,
+            } => PauseReason::Step,
+        };
+
+        *self = RunState::Paused {
+            reason: reason.clone(),
+            location,
+        };
+        Some(reason)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(self, RunState::Paused { .. })
+    }
+}
+
+impl DebugState {
+    /// the vm stops at the next hook it reaches
+    pub fn pause(&self, reason: PauseReason) {
+        let mut state = self.run_state.lock();
+        state.pause(reason);
+        self.sync_stop_pending(&state);
+    }
+
+    /// lets the vm run freely again, also drops a pending pause
+    pub fn unpause(&self) {
+        let mut state = self.run_state.lock();
+        state.unpause();
+        self.sync_stop_pending(&state);
+        self.run_state_changed.notify_all();
+    }
+
+    /// lets a paused vm run until the next hook
+    pub fn resume(&self) {
+        let mut state = self.run_state.lock();
+        if state.step(StepMode::NextHook) {
+            self.sync_stop_pending(&state);
+            self.run_state_changed.notify_all();
+        }
+    }
+
+    /// taken before unpausing to wait for the vm with `wait_until_released`
+    pub fn release_count(&self) -> usize {
+        self.releases.load(Ordering::Acquire)
+    }
+
+    /// waits for the vm thread to leave `checkpoint` after `release_count` returned `since`
+    ///
+    /// returns false if it's still in there after `timeout`
+    pub fn wait_until_released(&self, since: usize, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.blocked.load(Ordering::Acquire) && self.release_count() == since {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    pub fn state(&self) -> RunState {
+        self.run_state.lock().clone()
+    }
+
+    fn sync_stop_pending(&self, state: &RunState) {
+        self.stop_pending
+            .store(*state != RunState::Running, Ordering::Release);
+    }
+
+    /// whether the next hook might have to stop, only a hint for skipping `checkpoint`
+    pub fn stop_pending(&self) -> bool {
+        self.stop_pending.load(Ordering::Acquire)
+    }
+
+    fn max_pause(&self) -> Option<Duration> {
+        match self.max_pause_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms as u64)),
+        }
+    }
+
+    /// blocks the vm thread while paused, `on_stop` runs right before it starts waiting
+    pub fn checkpoint(
+        &self,
+        location: Location,
+        on_stop: impl FnOnce(&PauseReason, &Location),
+    ) -> Option<Stop> {
+        // a pending pause or step waits until the vm is out of skipped code
+        if !self.stop_pending() || self.in_skipped_code() {
+            return None;
+        }
+
+        let mut state = self.run_state.lock();
+        let reason = state.reach(location.clone())?;
+        on_stop(&reason, &location);
+        self.blocked.store(true, Ordering::Release);
+
+        let paused_at = Instant::now();
+        let max_pause = self.max_pause();
+        let deadline = max_pause.map(|max_pause| paused_at + max_pause);
+        let mut timed_out = None;
+        while state.is_paused() {
+            match deadline {
+                Some(deadline) => {
+                    if self
+                        .run_state_changed
+                        .wait_until(&mut state, deadline)
+                        .timed_out()
+                        && state.is_paused()
+                    {
+                        state.unpause();
+                        timed_out = max_pause;
+                    }
+                }
+                None => self.run_state_changed.wait(&mut state),
+            }
+        }
+        self.sync_stop_pending(&state);
+        self.blocked.store(false, Ordering::Release);
+        self.releases.fetch_add(1, Ordering::Release);
+        drop(state);
+        self.discount_pause(paused_at.elapsed());
+
+        Some(Stop { reason, timed_out })
+    }
+
+    /// returns the new generation
+    pub fn created(&self) -> usize {
+        self.reset();
+        self.alive.store(true, Ordering::Relaxed);
+        self.generation.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn destroyed(&self) -> usize {
+        self.alive.store(false, Ordering::Relaxed);
+        self.reset();
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// drops everything tied to the old vm, a pause that is only pending carries over to the new one
+    fn reset(&self) {
+        self.release_blocked();
+        self.call_depth.store(0, Ordering::Relaxed);
+        self.call_stack.lock().clear();
+        self.skip_depth.store(0, Ordering::Relaxed);
+        self.first_calls.lock().clear();
+    }
+
+    /// wakes the vm thread if it's actually stuck in `checkpoint`
+    fn release_blocked(&self) {
+        let mut state = self.run_state.lock();
+        if state.is_paused() {
+            state.unpause();
+            self.sync_stop_pending(&state);
+            self.run_state_changed.notify_all();
+        }
+    }
+
+    pub fn enter_traced_call(&self) -> TracedCall<'_> {
+        TracedCall {
+            state: self,
+            depth: self.call_depth.fetch_add(1, Ordering::Relaxed),
+            skipped: false,
+            _frame: None,
+            started: Instant::now(),
+            paused_before: self.paused_time(),
+        }
+    }
+
+    pub fn enter_call(&self, function: FunctionName) -> EnteredCall<'_> {
+        self.call_stack.lock().push(CallFrame {
+            function,
+            entered: Instant::now(),
+            reported: false,
+        });
+        EnteredCall { state: self }
+    }
+
+    /// the watchdog and the call tree time calls, not how long a vm sat paused inside of them
+    fn discount_pause(&self, paused: Duration) {
+        self.paused_nanos
+            .fetch_add(paused.as_nanos() as u64, Ordering::Relaxed);
+        for frame in self.call_stack.lock().iter_mut() {
+            frame.entered += paused;
+        }
+    }
+
+    /// only ever grows, calls take the difference from when they started
+    pub fn paused_time(&self) -> Duration {
+        Duration::from_nanos(self.paused_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn add_breakpoint(&self, function: impl Into<String>) {
+        self.breakpoints.lock().insert(function.into());
+        self.breakpoints_updated();
+    }
+
+    pub fn remove_breakpoint(&self, function: &str) {
+        self.breakpoints.lock().remove(function);
+        self.breakpoints_updated();
+    }
+
+    pub fn clear_breakpoints(&self) {
+        self.breakpoints.lock().clear();
+        self.temporary_breakpoints.lock().clear();
+        self.script_breakpoints.lock().clear();
+        self.data_breakpoints.lock().clear();
+        self.break_on_next_call.store(false, Ordering::Relaxed);
+        self.breakpoints_updated();
+    }
+
+    pub fn add_script_breakpoint(
+        &self,
+        pattern: &str,
+        kind: ScriptBreakKind,
+    ) -> Result<(), PatternError> {
+        let pattern = Pattern::new(pattern)?;
+        let mut script_breakpoints = self.script_breakpoints.lock();
+        if !script_breakpoints
+            .iter()
+            .any(|breakpoint| breakpoint.pattern == pattern && breakpoint.kind == kind)
+        {
+            script_breakpoints.push(ScriptBreakpoint { pattern, kind });
         }
+        drop(script_breakpoints);
+        self.breakpoints_updated();
+        Ok(())
+    }
+
+    pub fn remove_script_breakpoint(&self, pattern: &str, kind: ScriptBreakKind) {
+        self.script_breakpoints
+            .lock()
+            .retain(|breakpoint| breakpoint.pattern.as_str() != pattern || breakpoint.kind != kind);
+        self.breakpoints_updated();
+    }
+
+    fn script_breakpoint_matches(&self, file: &str, kind: ScriptBreakKind) -> bool {
+        self.script_breakpoints
+            .lock()
+            .iter()
+            .any(|breakpoint| breakpoint.kind == kind && breakpoint.pattern.matches(file))
+    }
+
+    /// checked by the compile hooks before the script is compiled
+    pub fn breaks_on_load(&self, file: &str) -> Option<PauseReason> {
+        (self.has_load_breakpoints.load(Ordering::Relaxed)
+            && !self.in_skipped_code()
+            && self.script_breakpoint_matches(file, ScriptBreakKind::Load))
+        .then(|| PauseReason::ScriptLoad(file.to_string()))
+    }
+
+    /// whether calls need their script resolved for first call breakpoints
+    pub fn has_first_call_breakpoints(&self) -> bool {
+        self.has_first_call_breakpoints.load(Ordering::Relaxed)
+    }
+
+    pub fn add_data_breakpoint(&self, predicate: DataPredicate) {
+        let mut data_breakpoints = self.data_breakpoints.lock();
+        if !data_breakpoints.contains(&predicate) {
+            data_breakpoints.push(predicate);
+        }
+        drop(data_breakpoints);
+        self.breakpoints_updated();
+    }
+
+    pub fn remove_data_breakpoint(&self, predicate: &DataPredicate) {
+        self.data_breakpoints
+            .lock()
+            .retain(|breakpoint| breakpoint != predicate);
+        self.breakpoints_updated();
+    }
+
+    /// lets the value hooks skip building a value when nothing could match it
+    pub fn has_data_breakpoints(&self) -> bool {
+        self.has_data_breakpoints.load(Ordering::Relaxed)
+    }
+
+    /// checked by the push hooks before the value is pushed and by the get hooks after it was fetched
+    pub fn breaks_on_value(
+        &self,
+        hook: &'static str,
+        position: i64,
+        value: &DataValue,
+    ) -> Option<PauseReason> {
+        if !self.has_data_breakpoints() || self.in_skipped_code() {
+            return None;
+        }
+
+        let data_breakpoints = self.data_breakpoints.lock();
+        let predicate = data_breakpoints
+            .iter()
+            .find(|predicate| predicate.matches(value))?;
+        Some(PauseReason::Value {
+            hook,
+            position,
+            value: value.to_string(),
+            predicate: predicate.to_string(),
+        })
+    }
+
+    /// swaps the breakpoints of the previous config for the ones of the new config, the others stay
+    ///
+    /// the script globs have to be valid already
+    pub fn replace_configured(&self, configured: ConfiguredBreakpoints) {
+        let previous = std::mem::replace(&mut *self.configured.lock(), configured);
+        for function in &previous.functions {
+            self.remove_breakpoint(function);
+        }
+        for (pattern, kind) in &previous.scripts {
+            self.remove_script_breakpoint(pattern, *kind);
+        }
+        for predicate in &previous.values {
+            self.remove_data_breakpoint(predicate);
+        }
+
+        let configured = self.configured.lock();
+        for function in &configured.functions {
+            self.add_breakpoint(function.clone());
+        }
+        for (pattern, kind) in &configured.scripts {
+            _ = self.add_script_breakpoint(pattern, *kind);
+        }
+        for predicate in &configured.values {
+            self.add_data_breakpoint(predicate.clone());
+        }
+    }
+
+    /// the function breakpoints the config didn't add, sorted
+    pub fn user_breakpoints(&self) -> Vec<String> {
+        let configured = self.configured.lock();
+        let mut breakpoints = self
+            .breakpoints
+            .lock()
+            .iter()
+            .filter(|function| !configured.functions.contains(function))
+            .cloned()
+            .collect::<Vec<String>>();
+        breakpoints.sort();
+        breakpoints
+    }
+
+    /// swaps the function breakpoints set from the ui or the console for `functions`, the configured ones stay
+    pub fn replace_user_breakpoints(&self, functions: &[String]) {
+        let configured = self.configured.lock();
+        let mut breakpoints = self.breakpoints.lock();
+        breakpoints.retain(|function| configured.functions.contains(function));
+        breakpoints.extend(functions.iter().cloned());
+        drop(breakpoints);
+        drop(configured);
+        self.breakpoints_updated();
+    }
+
+    /// the script breakpoints the config didn't add, sorted by glob
+    pub fn user_script_breakpoints(&self) -> Vec<(String, ScriptBreakKind)> {
+        let configured = self.configured.lock();
+        let mut script_breakpoints = self
+            .script_breakpoints
+            .lock()
+            .iter()
+            .map(|breakpoint| (breakpoint.pattern.as_str().to_string(), breakpoint.kind))
+            .filter(|script| !configured.scripts.contains(script))
+            .collect::<Vec<(String, ScriptBreakKind)>>();
+        script_breakpoints.sort_by(|(left, _), (right, _)| left.cmp(right));
+        script_breakpoints
+    }
+
+    /// swaps the script breakpoints set from the ui or the console for `scripts`, the configured ones stay
+    pub fn replace_user_script_breakpoints(&self, scripts: Vec<ScriptBreakpoint>) {
+        let configured = self.configured.lock();
+        let mut script_breakpoints = self.script_breakpoints.lock();
+        script_breakpoints.retain(|breakpoint| {
+            configured.scripts.iter().any(|(pattern, kind)| {
+                breakpoint.pattern.as_str() == pattern && breakpoint.kind == *kind
+            })
+        });
+        for script in scripts {
+            if !script_breakpoints.iter().any(|breakpoint| {
+                breakpoint.pattern == script.pattern && breakpoint.kind == script.kind
+            }) {
+                script_breakpoints.push(script);
+            }
+        }
+        drop(script_breakpoints);
+        drop(configured);
+        self.breakpoints_updated();
+    }
+
+    /// the data breakpoints the config didn't add, in the order they were added
+    pub fn user_data_breakpoints(&self) -> Vec<DataPredicate> {
+        let configured = self.configured.lock();
+        self.data_breakpoints
+            .lock()
+            .iter()
+            .filter(|predicate| !configured.values.contains(predicate))
+            .cloned()
+            .collect()
+    }
+
+    /// swaps the data breakpoints set from the ui or the console for `predicates`, the configured ones stay
+    pub fn replace_user_data_breakpoints(&self, predicates: Vec<DataPredicate>) {
+        let configured = self.configured.lock();
+        let mut data_breakpoints = self.data_breakpoints.lock();
+        data_breakpoints.retain(|predicate| configured.values.contains(predicate));
+        for predicate in predicates {
+            if !data_breakpoints.contains(&predicate) {
+                data_breakpoints.push(predicate);
+            }
+        }
+        drop(data_breakpoints);
+        drop(configured);
+        self.breakpoints_updated();
+    }
+
+    /// unpauses the vm and stops it again at the next `sq_call`, only native code calling into scripts goes through it
+    pub fn break_on_next_call(&self) {
+        self.break_on_next_call.store(true, Ordering::Relaxed);
+        self.refresh_has_breakpoints();
+        self.unpause();
+    }
+
+    /// unpauses the vm and stops it again once native code calls `function` through `sq_call`, the breakpoint is removed when hit
+    pub fn run_to(&self, function: impl Into<String>) {
+        self.temporary_breakpoints.lock().insert(function.into());
+        self.refresh_has_breakpoints();
+        self.unpause();
+    }
+
+    fn breakpoints_updated(&self) {
+        self.refresh_has_breakpoints();
+        self.breakpoints_changed.store(true, Ordering::Relaxed);
+    }
+
+    fn refresh_has_breakpoints(&self) {
+        let script_breakpoints = self.script_breakpoints.lock();
+        let has_kind = |kind| {
+            script_breakpoints
+                .iter()
+                .any(|breakpoint| breakpoint.kind == kind)
+        };
+        let has_first_call_breakpoints = has_kind(ScriptBreakKind::FirstCall);
+        self.has_first_call_breakpoints
+            .store(has_first_call_breakpoints, Ordering::Relaxed);
+        self.has_load_breakpoints
+            .store(has_kind(ScriptBreakKind::Load), Ordering::Relaxed);
+        drop(script_breakpoints);
+        self.has_data_breakpoints
+            .store(!self.data_breakpoints.lock().is_empty(), Ordering::Relaxed);
+
+        let has_breakpoints = self.break_on_next_call.load(Ordering::Relaxed)
+            || has_first_call_breakpoints
+            || !self.breakpoints.lock().is_empty()
+            || !self.temporary_breakpoints.lock().is_empty();
+        self.has_breakpoints
+            .store(has_breakpoints, Ordering::Release);
+    }
+
+    pub fn has_breakpoints(&self) -> bool {
+        self.has_breakpoints.load(Ordering::Acquire)
+    }
+
+    /// whether anything could stop this vm, only atomics so idle hooks can check it first
+    pub fn is_armed(&self) -> bool {
+        self.stop_pending()
+            || self.has_breakpoints()
+            || self.has_data_breakpoints()
+            || self.has_load_breakpoints.load(Ordering::Relaxed)
+    }
+
+    pub fn has_breakpoint(&self, function: &str) -> bool {
+        self.has_breakpoints() && self.breakpoints.lock().contains(function)
+    }
+
+    /// checks a call against every kind of breakpoint, temporary ones are used up
+    ///
+    /// `file` only has to be resolved if there are first call breakpoints
+    pub fn breaks_on_call(
+        &self,
+        function: &FunctionName,
+        file: Option<&str>,
+    ) -> Option<PauseReason> {
+        if !self.has_breakpoints() || self.in_skipped_code() {
+            return None;
+        }
+
+        if self.break_on_next_call.swap(false, Ordering::Relaxed) {
+            self.refresh_has_breakpoints();
+            return Some(PauseReason::NextCall(function.to_string()));
+        }
+
+        if let Some(file) = file.filter(|_| self.has_first_call_breakpoints()) {
+            if self.script_breakpoint_matches(file, ScriptBreakKind::FirstCall)
+                && self.first_calls.lock().insert(file.to_string())
+            {
+                return Some(PauseReason::FirstCall {
+                    function: function.to_string(),
+                    file: file.to_string(),
+                });
+            }
+        }
+
+        let name = function.as_str()?;
+        if self.temporary_breakpoints.lock().remove(name) {
+            self.refresh_has_breakpoints();
+            return Some(PauseReason::RunTo(name.to_string()));
+        }
+
+        self.has_breakpoint(name)
+            .then(|| PauseReason::Breakpoint(name.to_string()))
+    }
+
+    pub fn set_skip_list(&self, skip_list: SkipList) {
+        self.update_skip_list(|current| *current = skip_list);
+    }
+
+    pub fn update_skip_list(&self, update: impl FnOnce(&mut SkipList)) {
+        let mut skip_list = self.skip_list.lock();
+        update(&mut skip_list);
+        self.has_skips
+            .store(!skip_list.is_empty(), Ordering::Relaxed);
+    }
+
+    pub fn has_skips(&self) -> bool {
+        self.has_skips.load(Ordering::Relaxed)
+    }
+
+    /// returns whether the call is skipped, every skipped call has to be left with `leave_skipped`
+    fn enter_skipped(&self, function: Option<&str>, file: Option<&str>) -> bool {
+        let skipped = self.skip_list.lock().skips(function, file);
+        if skipped {
+            self.skip_depth.fetch_add(1, Ordering::Relaxed);
+        }
+        skipped
+    }
+
+    fn leave_skipped(&self) {
+        _ = self
+            .skip_depth
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |depth| {
+                depth.checked_sub(1)
+            });
+    }
+
+    pub fn in_skipped_code(&self) -> bool {
+        self.skip_depth.load(Ordering::Relaxed) != 0
+    }
+
+    /// skipped code is only left out of the trace if the skip list asks for it
+    pub fn hides_trace(&self) -> bool {
+        self.in_skipped_code() && self.skip_list.lock().hide
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn location() -> Location {
+        Location {
+            hook: "sq_call",
+            depth: 1,
+        }
+    }
+
+    /// waits until the vm thread is blocked in `checkpoint`
+    fn wait_until_paused(state: &DebugState) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !state.state().is_paused() {
+            assert!(Instant::now() < deadline, "the vm never paused");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn pause_keeps_the_first_reason() {
+        let mut state = RunState::Running;
+        state.pause(PauseReason::Requested);
+        state.pause(PauseReason::Script);
+        assert_eq!(state, RunState::PauseRequested(PauseReason::Requested));
+
+        state.unpause();
+        assert_eq!(state, RunState::Running);
+    }
+
+    #[test]
+    fn only_a_paused_vm_steps() {
+        let mut state = RunState::Running;
+        assert!(!state.step(StepMode::NextHook));
+
+        state.pause(PauseReason::Requested);
+        assert!(!state.step(StepMode::NextHook));
+
+        state.reach(location());
+        assert!(state.step(StepMode::NextHook));
+        assert_eq!(
+            state,
+            RunState::Stepping {
+                mode: StepMode::NextHook
+            }
+        );
+    }
+
+    #[test]
+    fn reach_stops_only_when_asked_to() {
+        let mut state = RunState::Running;
+        assert_eq!(state.reach(location()), None);
+
+        state.pause(PauseReason::Requested);
+        assert_eq!(state.reach(location()), Some(PauseReason::Requested));
+        assert!(state.is_paused());
+        // already stopped there
+        assert_eq!(state.reach(location()), None);
+
+        state.step(StepMode::NextHook);
+        assert_eq!(state.reach(location()), Some(PauseReason::Step));
+        assert_eq!(
+            state,
+            RunState::Paused {
+                reason: PauseReason::Step,
+                location: location()
+            }
+        );
+    }
+
+    #[test]
+    fn checkpoint_passes_without_a_pending_stop() {
+        let state = DebugState::default();
+        assert!(!state.stop_pending());
+        assert!(state
+            .checkpoint(location(), |_, _| panic!("nothing should stop"))
+            .is_none());
+
+        state.pause(PauseReason::Requested);
+        assert!(state.stop_pending());
+        state.unpause();
+        assert!(!state.stop_pending());
+        assert!(state.checkpoint(location(), |_, _| {}).is_none());
+    }
+
+    #[test]
+    fn checkpoint_blocks_until_unpaused() {
+        let state = DebugState::default();
+        state.pause(PauseReason::Requested);
+
+        thread::scope(|scope| {
+            let vm = scope.spawn(|| state.checkpoint(location(), |_, _| {}));
+            wait_until_paused(&state);
+            state.unpause();
+
+            let stop = vm.join().unwrap().expect("the vm should have stopped");
+            assert_eq!(stop.reason, PauseReason::Requested);
+            assert_eq!(stop.timed_out, None);
+        });
+
+        assert_eq!(state.state(), RunState::Running);
+        assert!(!state.stop_pending());
+    }
+
+    #[test]
+    fn resume_stops_again_at_the_next_hook() {
+        let state = DebugState::default();
+        state.pause(PauseReason::Requested);
+
+        thread::scope(|scope| {
+            let vm = scope.spawn(|| {
+                let first = state.checkpoint(location(), |_, _| {});
+                let second = state.checkpoint(location(), |_, _| {});
+                (first, second)
+            });
+
+            wait_until_paused(&state);
+            // stepping isn't paused so this only returns once the next checkpoint stopped
+            state.resume();
+            wait_until_paused(&state);
+            state.unpause();
+
+            let (first, second) = vm.join().unwrap();
+            assert_eq!(first.unwrap().reason, PauseReason::Requested);
+            assert_eq!(second.unwrap().reason, PauseReason::Step);
+        });
+    }
+
+    #[test]
+    fn released_once_the_vm_left_the_checkpoint() {
+        let state = DebugState::default();
+        assert!(state.wait_until_released(state.release_count(), Duration::ZERO));
+        state.pause(PauseReason::Requested);
+
+        thread::scope(|scope| {
+            let vm = scope.spawn(|| {
+                state.checkpoint(location(), |_, _| {});
+                // stepped right into the next pause
+                state.checkpoint(location(), |_, _| {});
+            });
+            wait_until_paused(&state);
+            let since = state.release_count();
+            assert!(!state.wait_until_released(since, Duration::from_millis(10)));
+
+            state.resume();
+            assert!(state.wait_until_released(since, Duration::from_secs(5)));
+
+            wait_until_paused(&state);
+            state.unpause();
+            vm.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn resume_does_nothing_unless_paused() {
+        let state = DebugState::default();
+        state.resume();
+        assert_eq!(state.state(), RunState::Running);
+        assert!(!state.stop_pending());
+    }
+
+    #[test]
+    fn pauses_dont_count_towards_the_call() {
+        let state = DebugState::default();
+        let frame = state.enter_call(FunctionName::Unknown);
+        let entered = state.call_stack.lock()[0].entered;
+
+        state.max_pause_ms.store(20, Ordering::Relaxed);
+        state.pause(PauseReason::Requested);
+        state.checkpoint(location(), |_, _| {});
+
+        assert!(state.call_stack.lock()[0].entered >= entered + Duration::from_millis(20));
+        drop(frame);
+        assert!(state.call_stack.lock().is_empty());
+    }
+
+    #[test]
+    fn traced_calls_dont_time_their_pauses() {
+        let state = DebugState::default();
+        let mut call = state.enter_traced_call();
+        call.start_timing();
+
+        state.max_pause_ms.store(20, Ordering::Relaxed);
+        state.pause(PauseReason::Requested);
+        state.checkpoint(location(), |_, _| {});
+
+        assert!(state.paused_time() >= Duration::from_millis(20));
+        assert!(call.elapsed() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn traced_calls_are_left_when_a_hook_panics() {
+        let state = DebugState::default();
+        state.set_skip_list(SkipList {
+            functions: HashSet::from(["UpdateMainHudVisibility".to_string()]),
+            ..Default::default()
+        });
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut call = state.enter_traced_call();
+            call.enter_skipped(Some("UpdateMainHudVisibility"), None);
+            call.enter_call(FunctionName::Unknown);
+            assert_eq!(state.call_depth.load(Ordering::Relaxed), 1);
+            assert!(state.in_skipped_code());
+            panic!("the hook panicked");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(state.call_depth.load(Ordering::Relaxed), 0);
+        assert!(!state.in_skipped_code());
+        assert!(state.call_stack.lock().is_empty());
+    }
+
+    #[test]
+    fn user_breakpoints_leave_the_configured_ones_alone() {
+        let state = DebugState::default();
+        state.replace_configured(ConfiguredBreakpoints {
+            functions: vec!["CodeCallback_OnClientConnected".to_string()],
+            ..Default::default()
+        });
+        state.add_breakpoint("OnDamage");
+        state.run_to("GiveLoadout");
+        assert_eq!(state.user_breakpoints(), ["OnDamage"]);
+
+        state.replace_user_breakpoints(&["UpdateHud".to_string()]);
+        assert_eq!(state.user_breakpoints(), ["UpdateHud"]);
+        assert!(state.has_breakpoint("CodeCallback_OnClientConnected"));
+        assert!(!state.has_breakpoint("OnDamage"));
+        assert!(state.temporary_breakpoints.lock().contains("GiveLoadout"));
+
+        // a breakpoint removed from the config is gone for good
+        state.replace_configured(ConfiguredBreakpoints::default());
+        assert!(!state.has_breakpoint("CodeCallback_OnClientConnected"));
+        assert_eq!(state.user_breakpoints(), ["UpdateHud"]);
+    }
+
+    #[test]
+    fn user_script_breakpoints_leave_the_configured_ones_alone() {
+        let state = DebugState::default();
+        state.replace_configured(ConfiguredBreakpoints {
+            scripts: vec![("*/sh_*.gnut".to_string(), ScriptBreakKind::Load)],
+            ..Default::default()
+        });
+        state
+            .add_script_breakpoint("*/cl_hud.gnut", ScriptBreakKind::FirstCall)
+            .unwrap();
+        assert_eq!(
+            state.user_script_breakpoints(),
+            [("*/cl_hud.gnut".to_string(), ScriptBreakKind::FirstCall)]
+        );
+
+        state.replace_user_script_breakpoints(vec![ScriptBreakpoint {
+            pattern: Pattern::new("*/ui_*.nut").unwrap(),
+            kind: ScriptBreakKind::Load,
+        }]);
+        assert_eq!(
+            state.user_script_breakpoints(),
+            [("*/ui_*.nut".to_string(), ScriptBreakKind::Load)]
+        );
+        assert!(state
+            .breaks_on_load("scripts/vscripts/sh_utility.gnut")
+            .is_some());
+        assert!(!state.has_first_call_breakpoints());
+    }
+
+    #[test]
+    fn user_data_breakpoints_leave_the_configured_ones_alone() {
+        let state = DebugState::default();
+        let predicate = |source: &str| source.parse::<DataPredicate>().unwrap();
+        state.replace_configured(ConfiguredBreakpoints {
+            values: vec![predicate("=mp_titan")],
+            ..Default::default()
+        });
+        state.add_data_breakpoint(predicate("0..10"));
+        assert_eq!(state.user_data_breakpoints(), [predicate("0..10")]);
+
+        state.replace_user_data_breakpoints(vec![predicate("/^weapon_/")]);
+        assert_eq!(state.user_data_breakpoints(), [predicate("/^weapon_/")]);
+        assert!(state
+            .breaks_on_value(
+                "sq_pushstring",
+                -1,
+                &DataValue::String("mp_titan".to_string())
+            )
+            .is_some());
+        assert!(state
+            .breaks_on_value("sq_pushinteger", -1, &DataValue::Integer(5))
+            .is_none());
+    }
+
+    #[test]
+    fn checkpoint_times_out() {
+        let state = DebugState::default();
+        state.max_pause_ms.store(20, Ordering::Relaxed);
+        state.pause(PauseReason::Requested);
+
+        let mut stopped = None;
+        let stop = state
+            .checkpoint(location(), |reason, location| {
+                stopped = Some((reason.clone(), location.clone()))
+            })
+            .expect("the vm should have stopped");
+
+        assert_eq!(stopped, Some((PauseReason::Requested, location())));
+        assert_eq!(stop.timed_out, Some(Duration::from_millis(20)));
+        assert_eq!(state.state(), RunState::Running);
+        assert!(!state.stop_pending());
     }
 }
@@ -0,0 +1,40 @@
+use parking_lot::Mutex;
+
+pub struct Diagnostic {
+    pub message: String,
+    pub count: usize,
+}
+
+/// internal errors that were swallowed instead of crashing the game
+#[derive(Default)]
+pub struct Diagnostics {
+    errors: Mutex<Vec<Diagnostic>>,
+}
+
+impl Diagnostics {
+    /// repeated errors only bump a counter since hooks can fail thousands of times a frame
+    pub fn report(&self, message: impl Into<String>) {
+        let message = message.into();
+        let mut errors = self.errors.lock();
+
+        match errors.iter_mut().find(|error| error.message == message) {
+            Some(error) => error.count += 1,
+            None => {
+                log::warn!("{message}");
+                errors.push(Diagnostic { message, count: 1 });
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<(String, usize)> {
+        self.errors
+            .lock()
+            .iter()
+            .map(|error| (error.message.clone(), error.count))
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.errors.lock().clear();
+    }
+}
@@ -0,0 +1,89 @@
+use crossbeam_queue::ArrayQueue;
+use rrplug::prelude::*;
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::stacktrace::StackTrace;
+
+/// events hold their values inline, this is a few mb of slots
+const EVENT_QUEUE_CAPACITY: usize = 1 << 16;
+/// keeps a single frame from stalling on a flood of events, the rest waits for the next frame
+pub const MAX_EVENTS_PER_FRAME: usize = 1 << 14;
+
+/// hands trace events from every vm thread to the ui without locking
+///
+/// the slots are allocated once, when the consumer falls behind new events are dropped
+pub struct EventQueue {
+    queue: ArrayQueue<(ScriptContext, StackTrace)>,
+    produced: AtomicUsize,
+    consumed: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct EventCounters {
+    pub produced: usize,
+    pub consumed: usize,
+    pub dropped: usize,
+    pub queued: usize,
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self {
+            queue: ArrayQueue::new(EVENT_QUEUE_CAPACITY),
+            produced: AtomicUsize::new(0),
+            consumed: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Display for EventCounters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{} produced, {} consumed, {} dropped, {} queued",
+            self.produced, self.consumed, self.dropped, self.queued
+        ))
+    }
+}
+
+impl EventQueue {
+    pub fn push(&self, context: ScriptContext, trace: StackTrace) {
+        self.produced.fetch_add(1, Ordering::Relaxed);
+        if self.queue.push((context, trace)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// takes up to `max` events, returns how many were handled
+    pub fn drain(&self, max: usize, mut handle: impl FnMut(ScriptContext, StackTrace)) -> usize {
+        let mut count = 0;
+        while count < max {
+            let Some((context, trace)) = self.queue.pop() else {
+                break;
+            };
+            handle(context, trace);
+            count += 1;
+        }
+
+        self.consumed.fetch_add(count, Ordering::Relaxed);
+        count
+    }
+
+    /// drops everything still queued without counting it as consumed
+    pub fn discard(&self) {
+        while self.queue.pop().is_some() {}
+    }
+
+    pub fn counters(&self) -> EventCounters {
+        EventCounters {
+            produced: self.produced.load(Ordering::Relaxed),
+            consumed: self.consumed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            queued: self.queue.len(),
+        }
+    }
+}
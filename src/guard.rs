@@ -0,0 +1,112 @@
+use rrplug::prelude::*;
+use std::{
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
+    fs::OpenOptions,
+    io::Write,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{crashdump::write_crash_dump, exports::PLUGIN, hooks::HOOK_NAMES};
+
+const CRASH_LOG_PATH: &str = "R2Northstar/logs/dbgquirrel_crash.txt";
+
+/// set for a hook once it panicked, it only runs the original function from then on
+static POISONED: [AtomicBool; HOOK_NAMES.len()] =
+    [const { AtomicBool::new(false) }; HOOK_NAMES.len()];
+
+thread_local! {
+    /// filled by the panic hook since the backtrace is gone once `catch_unwind` returns
+    static LAST_PANIC: RefCell<Option<String>> = RefCell::new(None);
+    /// how many `guarded` calls this thread is inside of, their panics are caught and the game keeps running
+    static GUARD_DEPTH: Cell<usize> = Cell::new(0);
+}
+
+/// keeps the backtrace of a panic around for whoever catches it and writes a crash dump
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let report = format!("{info}\n{}", Backtrace::force_capture());
+        let recovered = GUARD_DEPTH.with(|depth| depth.get() != 0);
+        write_crash_dump(&report, recovered);
+        LAST_PANIC.with(|last| _ = last.borrow_mut().replace(report));
+        previous(info);
+    }));
+}
+
+fn poisoned(hook: &str) -> Option<&'static AtomicBool> {
+    HOOK_NAMES
+        .iter()
+        .position(|name| *name == hook)
+        .map(|i| &POISONED[i])
+}
+
+/// every hook that panicked so far
+pub fn poisoned_hooks() -> impl Iterator<Item = &'static str> {
+    HOOK_NAMES
+        .iter()
+        .zip(&POISONED)
+        .filter(|(_, poisoned)| poisoned.load(Ordering::Relaxed))
+        .map(|(name, _)| *name)
+}
+
+/// runs the debugger side of a hook, a panic can't unwind into the game so it's caught and the hook is turned off
+pub fn guarded<R>(hook: &'static str, body: impl FnOnce() -> R) -> Option<R> {
+    let poisoned = poisoned(hook);
+    if poisoned.is_some_and(|poisoned| poisoned.load(Ordering::Relaxed)) {
+        return None;
+    }
+
+    GUARD_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(body));
+    GUARD_DEPTH.with(|depth| depth.set(depth.get() - 1));
+
+    match result {
+        Ok(result) => Some(result),
+        Err(_) => {
+            if let Some(poisoned) = poisoned {
+                poisoned.store(true, Ordering::Relaxed);
+            }
+            _ = panic::catch_unwind(|| disable_hook(hook));
+            None
+        }
+    }
+}
+
+fn disable_hook(hook: &'static str) {
+    let report = LAST_PANIC
+        .with(|last| last.borrow_mut().take())
+        .unwrap_or_else(|| "panicked without a report".to_string());
+    let report = format!("{hook} panicked and was disabled: {report}");
+
+    log::error!("{report}");
+    write_crash_log(Path::new(CRASH_LOG_PATH), &report);
+
+    let plugin = PLUGIN.wait();
+    plugin.recording.write_to_sink(&report);
+    plugin
+        .diagnostics
+        .report(format!("{hook} panicked and was disabled"));
+
+    // the detour itself can only be disabled on the engine thread
+    plugin
+        .settings
+        .disabled_hooks
+        .lock()
+        .insert(hook.to_string());
+    plugin.settings.hooks_dirty.store(true, Ordering::Relaxed);
+}
+
+fn write_crash_log(path: &Path, report: &str) {
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{report}\n"));
+
+    if let Err(err) = result {
+        log::error!("failed to write {}: {err}", path.display());
+    }
+}
@@ -0,0 +1,215 @@
+use rrplug::prelude::*;
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream},
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use crate::{
+    config::UiConfig, context_from_str, debug::PauseReason, exports::PLUGIN,
+    session::save_session_if_changed, ALL_CONTEXTS,
+};
+
+const DEFAULT_PORT: u16 = 9871;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `-dbgq_headless` always wins, then `ui.headless` from the config, otherwise only dedicated servers are headless
+pub fn is_headless(ui: &UiConfig) -> bool {
+    if std::env::args().any(|arg| arg == "-dbgq_headless") {
+        return true;
+    }
+    ui.headless.unwrap_or_else(is_dedicated)
+}
+
+/// dedicated servers have no display so the window would only fail or steal a thread
+fn is_dedicated() -> bool {
+    std::env::args().any(|arg| arg == "-dedicated") || !has_display()
+}
+
+#[cfg(windows)]
+fn has_display() -> bool {
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetSystemMetrics(index: i32) -> i32;
+    }
+
+    const SM_CMONITORS: i32 = 80;
+    unsafe { GetSystemMetrics(SM_CMONITORS) > 0 }
+}
+
+#[cfg(not(windows))]
+fn has_display() -> bool {
+    std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+fn remote_port() -> u16 {
+    let args = std::env::args().collect::<Vec<String>>();
+    args.iter()
+        .position(|arg| arg == "-dbgq_port")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT)
+}
+
+pub fn run() {
+    let remote = std::thread::spawn(run_remote);
+
+    let plugin = PLUGIN.wait();
+    while !plugin.is_shutting_down() {
+        let filters = plugin.settings.filters.lock().clone();
+        plugin.events.drain(usize::MAX, |context, log| {
+            if filters.allows(&log) {
+                plugin.recording.record(context, &log)
+            }
+        });
+        save_session_if_changed(plugin);
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    plugin.events.discard();
+
+    _ = remote.join();
+}
+
+/// a line based control endpoint on localhost since there is no window to click on
+fn run_remote() {
+    let port = remote_port();
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("failed to bind the remote endpoint on port {port}: {err}");
+            return;
+        }
+    };
+
+    // polling so the thread can stop before the plugin is unloaded
+    if let Err(err) = listener.set_nonblocking(true) {
+        log::error!("failed to setup the remote endpoint: {err}");
+        return;
+    }
+
+    log::info!("dbgquirrel remote endpoint listening on 127.0.0.1:{port}");
+
+    let plugin = PLUGIN.wait();
+    while !plugin.is_shutting_down() {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                log::warn!("remote client failed to connect: {err}");
+                continue;
+            }
+        };
+
+        plugin.attached.store(true, Ordering::Relaxed);
+        if let Err(err) = handle_client(stream) {
+            log::warn!("remote client disconnected: {err}");
+        }
+        plugin.attached.store(false, Ordering::Relaxed);
+    }
+}
+
+fn handle_client(stream: TcpStream) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    while !PLUGIN.wait().is_shutting_down() {
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                let response = execute_command(line.split_whitespace().collect());
+                writeln!(writer, "{response}")?;
+                line.clear();
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+fn execute_command(args: Vec<&str>) -> String {
+    let Some((command, args)) = args.split_first() else {
+        return String::new();
+    };
+
+    let plugin = PLUGIN.wait();
+    match *command {
+        "pause_all" => {
+            plugin.pause_all(PauseReason::Requested);
+            return "ok".to_string();
+        }
+        "run_all" => {
+            plugin.unpause_all();
+            return "ok".to_string();
+        }
+        "step_all" => {
+            plugin.step_all();
+            return "ok".to_string();
+        }
+        // the vm comes last like for dbgq_run_to and dbgq_break
+        "run_to" => {
+            let Some(function) = args.first() else {
+                return "usage: run_to <func> [vm]".to_string();
+            };
+            let contexts = match args.get(1) {
+                Some(vm) => match context_from_str(vm) {
+                    Some(context) => vec![context],
+                    None => return "expected a vm: server, client or ui".to_string(),
+                },
+                None => ALL_CONTEXTS.to_vec(),
+            };
+
+            for context in contexts {
+                plugin.debug_info.get(context).run_to(*function);
+            }
+            return "ok".to_string();
+        }
+        "events" => return plugin.events.counters().to_string(),
+        "diagnostics" => {
+            return plugin
+                .diagnostics
+                .snapshot()
+                .into_iter()
+                .map(|(message, count)| format!("{message} (x{count})"))
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+        _ => {}
+    }
+
+    let Some(context) = args.first().and_then(|vm| context_from_str(vm)) else {
+        return "expected a vm: server, client or ui".to_string();
+    };
+    let state = plugin.debug_info.get(context);
+
+    match *command {
+        "pause" => state.pause(PauseReason::Requested),
+        "run" => state.unpause(),
+        "step" => state.resume(),
+        "state" => return state.state().to_string(),
+        "next" => state.break_on_next_call(),
+        "log" => {
+            let count = args
+                .get(1)
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(50);
+            return plugin.recording.last(context, count).join("\n");
+        }
+        _ => {
+            return format!(
+                "unknown command {command}; try pause, run (run freely), step (step to the next hook), next (stop at the next native to script sq_call), run_to <func> [vm] (stop when native code calls it), pause_all, run_all (run every vm freely), step_all (step every paused vm), state, log, events or diagnostics"
+            )
+        }
+    }
+
+    "ok".to_string()
+}
@@ -9,15 +9,96 @@ use rrplug::{
     mid::utils::from_char_ptr,
     prelude::*,
 };
-use std::cell::{Ref, RefCell};
+use std::{
+    cell::{Cell, Ref, RefCell},
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
 
-use crate::{exports::PLUGIN, sqvm_to_context, stacktrace::StackTrace};
+use crate::{
+    databreak::DataValue,
+    debug::{Location, PauseReason, Stop},
+    events::EventQueue,
+    exports::PLUGIN,
+    guard::guarded,
+    sqvm_to_context,
+    stacktrace::{FunctionName, StackTrace, TraceValue},
+    watchdog::watchdog_enabled,
+    DebugPlugin,
+};
 
 pub static CLIENT_DETOURS: EngineGlobal<RefCell<Option<DetouredSquirrelFunctions>>> =
     EngineGlobal::new(RefCell::new(None));
 pub static SERVER_DETOURS: EngineGlobal<RefCell<Option<DetouredSquirrelFunctions>>> =
     EngineGlobal::new(RefCell::new(None));
 
+thread_local! {
+    /// set while the original `sq_compilefile` runs, it compiles through `sq_compilebuffer` which shouldn't stop on the same script again
+    static COMPILING_FILE: Cell<bool> = Cell::new(false);
+}
+
+#[cfg(feature = "bench")]
+thread_local! {
+    /// set by the bench so the events of its simulated vm stay out of the real trace
+    pub(crate) static BENCH_EVENTS: Cell<Option<&'static EventQueue>> = Cell::new(None);
+}
+
+/// the untouched functions of each dll, once its detours are gone these run the real thing
+static CLIENT_ORIGINALS: OnceLock<SquirrelFunctions> = OnceLock::new();
+static SERVER_ORIGINALS: OnceLock<SquirrelFunctions> = OnceLock::new();
+
+macro_rules! detours {
+    ($callback:ident) => {
+        $callback!(
+            sq_defconst,
+            sq_compilebuffer,
+            sq_call,
+            sq_raiseerror,
+            sq_compilefile,
+            sq_newarray,
+            sq_arrayappend,
+            sq_newtable,
+            sq_newslot,
+            sq_pushroottable,
+            sq_pushstring,
+            sq_pushinteger,
+            sq_pushfloat,
+            sq_pushbool,
+            sq_pushasset,
+            sq_pushvector,
+            sq_pushobject,
+            sq_getstring,
+            sq_getinteger,
+            sq_getfloat,
+            sq_getbool,
+            sq_get,
+            sq_getasset,
+            sq_getuserdata,
+            sq_getvector,
+            sq_getthisentity,
+            sq_getobject,
+            sq_stackinfos,
+            sq_createuserdata,
+            sq_setuserdatatypeid,
+            sq_getfunction,
+            sq_getentityfrominstance,
+            sq_pushnewstructinstance,
+            sq_sealstructslot
+        )
+    };
+}
+
+macro_rules! hook_names {
+    ($($detour:ident),*) => {
+        &[$(stringify!($detour)),*]
+    };
+}
+
+pub const HOOK_NAMES: &[&str] = detours!(hook_names);
+
 pub struct DetouredSquirrelFunctions {
     // pub register_squirrel_func: RegisterSquirrelFuncType, // hooked by northstar :(
     pub sq_defconst: GenericDetour<sq_defconstType>,
@@ -57,629 +138,1018 @@ pub struct DetouredSquirrelFunctions {
 }
 
 impl DetouredSquirrelFunctions {
-    pub fn try_new(funcs: &SquirrelFunctions) -> Result<Self, RetourError> {
+    pub fn try_new<D: Dll>(funcs: &SquirrelFunctions) -> Result<Self, RetourError> {
         unsafe {
             let detours = Self {
-                sq_defconst: GenericDetour::new(funcs.sq_defconst, hook_sq_defconst)?,
+                sq_defconst: GenericDetour::new(funcs.sq_defconst, hook_sq_defconst::<D>)?,
                 sq_compilebuffer: GenericDetour::new(
                     funcs.sq_compilebuffer,
-                    hook_sq_compilebuffer,
+                    hook_sq_compilebuffer::<D>,
                 )?,
-                sq_call: GenericDetour::new(funcs.sq_call, hook_sq_call)?,
-                sq_raiseerror: GenericDetour::new(funcs.sq_raiseerror, hook_sq_raiseerror)?,
-                sq_compilefile: GenericDetour::new(funcs.sq_compilefile, hook_sq_compilefile)?,
-                sq_newarray: GenericDetour::new(funcs.sq_newarray, hook_sq_newarray)?,
-                sq_arrayappend: GenericDetour::new(funcs.sq_arrayappend, hook_sq_arrayappend)?,
-                sq_newtable: GenericDetour::new(funcs.sq_newtable, hook_sq_newtable)?,
-                sq_newslot: GenericDetour::new(funcs.sq_newslot, hook_sq_newslot)?,
+                sq_call: GenericDetour::new(funcs.sq_call, hook_sq_call::<D>)?,
+                sq_raiseerror: GenericDetour::new(funcs.sq_raiseerror, hook_sq_raiseerror::<D>)?,
+                sq_compilefile: GenericDetour::new(funcs.sq_compilefile, hook_sq_compilefile::<D>)?,
+                sq_newarray: GenericDetour::new(funcs.sq_newarray, hook_sq_newarray::<D>)?,
+                sq_arrayappend: GenericDetour::new(funcs.sq_arrayappend, hook_sq_arrayappend::<D>)?,
+                sq_newtable: GenericDetour::new(funcs.sq_newtable, hook_sq_newtable::<D>)?,
+                sq_newslot: GenericDetour::new(funcs.sq_newslot, hook_sq_newslot::<D>)?,
                 sq_pushroottable: GenericDetour::new(
                     funcs.sq_pushroottable,
-                    hook_sq_pushroottable,
+                    hook_sq_pushroottable::<D>,
                 )?,
-                sq_pushstring: GenericDetour::new(funcs.sq_pushstring, hook_sq_pushstring)?,
-                sq_pushinteger: GenericDetour::new(funcs.sq_pushinteger, hook_sq_pushinteger)?,
-                sq_pushfloat: GenericDetour::new(funcs.sq_pushfloat, hook_sq_pushfloat)?,
-                sq_pushbool: GenericDetour::new(funcs.sq_pushbool, hook_sq_pushbool)?,
-                sq_pushasset: GenericDetour::new(funcs.sq_pushasset, hook_sq_pushasset)?,
-                sq_pushvector: GenericDetour::new(funcs.sq_pushvector, hook_sq_pushvector)?,
-                sq_pushobject: GenericDetour::new(funcs.sq_pushobject, hook_sq_pushobject)?,
-                sq_getstring: GenericDetour::new(funcs.sq_getstring, hook_sq_getstring)?,
-                sq_getinteger: GenericDetour::new(funcs.sq_getinteger, hook_sq_getinteger)?,
-                sq_getfloat: GenericDetour::new(funcs.sq_getfloat, hook_sq_getfloat)?,
-                sq_getbool: GenericDetour::new(funcs.sq_getbool, hook_sq_getbool)?,
-                sq_get: GenericDetour::new(funcs.sq_get, hook_sq_get)?,
-                sq_getasset: GenericDetour::new(funcs.sq_getasset, hook_sq_getasset)?,
-                sq_getuserdata: GenericDetour::new(funcs.sq_getuserdata, hook_sq_getuserdata)?,
-                sq_getvector: GenericDetour::new(funcs.sq_getvector, hook_sq_getvector)?,
+                sq_pushstring: GenericDetour::new(funcs.sq_pushstring, hook_sq_pushstring::<D>)?,
+                sq_pushinteger: GenericDetour::new(funcs.sq_pushinteger, hook_sq_pushinteger::<D>)?,
+                sq_pushfloat: GenericDetour::new(funcs.sq_pushfloat, hook_sq_pushfloat::<D>)?,
+                sq_pushbool: GenericDetour::new(funcs.sq_pushbool, hook_sq_pushbool::<D>)?,
+                sq_pushasset: GenericDetour::new(funcs.sq_pushasset, hook_sq_pushasset::<D>)?,
+                sq_pushvector: GenericDetour::new(funcs.sq_pushvector, hook_sq_pushvector::<D>)?,
+                sq_pushobject: GenericDetour::new(funcs.sq_pushobject, hook_sq_pushobject::<D>)?,
+                sq_getstring: GenericDetour::new(funcs.sq_getstring, hook_sq_getstring::<D>)?,
+                sq_getinteger: GenericDetour::new(funcs.sq_getinteger, hook_sq_getinteger::<D>)?,
+                sq_getfloat: GenericDetour::new(funcs.sq_getfloat, hook_sq_getfloat::<D>)?,
+                sq_getbool: GenericDetour::new(funcs.sq_getbool, hook_sq_getbool::<D>)?,
+                sq_get: GenericDetour::new(funcs.sq_get, hook_sq_get::<D>)?,
+                sq_getasset: GenericDetour::new(funcs.sq_getasset, hook_sq_getasset::<D>)?,
+                sq_getuserdata: GenericDetour::new(funcs.sq_getuserdata, hook_sq_getuserdata::<D>)?,
+                sq_getvector: GenericDetour::new(funcs.sq_getvector, hook_sq_getvector::<D>)?,
                 sq_getthisentity: GenericDetour::new(
                     funcs.sq_getthisentity,
-                    hook_sq_getthisentity,
+                    hook_sq_getthisentity::<D>,
                 )?,
-                sq_getobject: GenericDetour::new(funcs.sq_getobject, hook_sq_getobject)?,
-                sq_stackinfos: GenericDetour::new(funcs.sq_stackinfos, hook_sq_stackinfos)?,
+                sq_getobject: GenericDetour::new(funcs.sq_getobject, hook_sq_getobject::<D>)?,
+                sq_stackinfos: GenericDetour::new(funcs.sq_stackinfos, hook_sq_stackinfos::<D>)?,
                 sq_createuserdata: GenericDetour::new(
                     funcs.sq_createuserdata,
-                    hook_sq_createuserdata,
+                    hook_sq_createuserdata::<D>,
                 )?,
                 sq_setuserdatatypeid: GenericDetour::new(
                     funcs.sq_setuserdatatypeid,
-                    hook_sq_setuserdatatypeid,
+                    hook_sq_setuserdatatypeid::<D>,
                 )?,
-                sq_getfunction: GenericDetour::new(funcs.sq_getfunction, hook_sq_getfunction)?,
+                sq_getfunction: GenericDetour::new(funcs.sq_getfunction, hook_sq_getfunction::<D>)?,
                 sq_getentityfrominstance: GenericDetour::new(
                     funcs.sq_getentityfrominstance,
-                    hook_sq_getentityfrominstance,
+                    hook_sq_getentityfrominstance::<D>,
                 )?,
 
                 sq_pushnewstructinstance: GenericDetour::new(
                     funcs.sq_pushnewstructinstance,
-                    hook_sq_pushnewstructinstance,
+                    hook_sq_pushnewstructinstance::<D>,
                 )?,
                 sq_sealstructslot: GenericDetour::new(
                     funcs.sq_sealstructslot,
-                    hook_sq_sealstructslot,
+                    hook_sq_sealstructslot::<D>,
                 )?,
             };
             Ok(detours)
         }
     }
 
-    fn enable(self) -> Result<Self, RetourError> {
+    fn set_enabled(&self, enabled: bool, disabled: &HashSet<String>) -> Result<(), RetourError> {
+        macro_rules! toggle {
+            ($($detour:ident),* $(,)?) => {
+                $(
+                    if enabled && !disabled.contains(stringify!($detour)) {
+                        self.$detour.enable()?;
+                    } else {
+                        self.$detour.disable()?;
+                    }
+                )*
+            };
+        }
+
         unsafe {
-            self.sq_defconst.enable()?;
-            self.sq_compilebuffer.enable()?;
-            self.sq_call.enable()?;
-            self.sq_raiseerror.enable()?;
-            self.sq_compilefile.enable()?;
-            self.sq_newarray.enable()?;
-            self.sq_arrayappend.enable()?;
-            self.sq_newtable.enable()?;
-            self.sq_newslot.enable()?;
-            self.sq_pushroottable.enable()?;
-            self.sq_pushstring.enable()?;
-            self.sq_pushinteger.enable()?;
-            self.sq_pushfloat.enable()?;
-            self.sq_pushbool.enable()?;
-            self.sq_pushasset.enable()?;
-            self.sq_pushvector.enable()?;
-            self.sq_pushobject.enable()?;
-            self.sq_getstring.enable()?;
-            self.sq_getinteger.enable()?;
-            self.sq_getfloat.enable()?;
-            self.sq_getbool.enable()?;
-            self.sq_get.enable()?;
-            self.sq_getasset.enable()?;
-            self.sq_getuserdata.enable()?;
-            self.sq_getvector.enable()?;
-            self.sq_getthisentity.enable()?;
-            self.sq_getobject.enable()?;
-            self.sq_stackinfos.enable()?;
-            self.sq_createuserdata.enable()?;
-            self.sq_setuserdatatypeid.enable()?;
-            self.sq_getfunction.enable()?;
-            self.sq_getentityfrominstance.enable()?;
-            self.sq_pushnewstructinstance.enable()?;
-            self.sq_sealstructslot.enable()?;
+            detours!(toggle);
         }
-        Ok(self)
+        Ok(())
     }
 }
 
-pub fn init_hooks(dll: &WhichDll, engine_token: EngineToken) {
-    match dll {
-        WhichDll::Client => {
-            _ = CLIENT_DETOURS.get(engine_token).borrow_mut().replace(
-                DetouredSquirrelFunctions::try_new(
-                    &SQUIRREL_CLIENT_FUNCS
-                        .get()
-                        .expect("client functions where not init at this time, weird!")
-                        .into(),
-                )
-                .expect("failed to init client hooks")
-                .enable()
-                .expect("failed to enable hooks"),
-            )
+/// brings every detour in line with the hook settings
+pub fn apply_hook_settings(engine_token: EngineToken) {
+    let settings = &PLUGIN.wait().settings;
+    let enabled = settings.hooks_enabled.load(Ordering::Relaxed);
+    let disabled = settings.disabled_hooks.lock().clone();
+
+    for detours in [&CLIENT_DETOURS, &SERVER_DETOURS] {
+        if let Some(detours) = detours.get(engine_token).borrow().as_ref() {
+            if let Err(err) = detours.set_enabled(enabled, &disabled) {
+                log::error!("failed to toggle hooks: {err}");
+            }
         }
-        WhichDll::Server => {
-            _ = SERVER_DETOURS.get(engine_token).borrow_mut().replace(
-                DetouredSquirrelFunctions::try_new(
-                    &SQUIRREL_SERVER_FUNCS
-                        .get()
-                        .expect("server functions where not init at this time, weird!")
-                        .into(),
-                )
-                .expect("failed to init client hooks")
-                .enable()
-                .expect("failed to enable hooks"),
-            )
+    }
+}
+
+/// disables and drops every detour, if one is still borrowed by a running hook it is only disabled
+///
+/// disabled first so a hook that runs in between finds the functions unpatched when it calls the original
+pub fn teardown_hooks(engine_token: EngineToken) {
+    for detours in [&CLIENT_DETOURS, &SERVER_DETOURS] {
+        let detours = detours.get(engine_token);
+        let result = detours
+            .borrow()
+            .as_ref()
+            .map(|detours| detours.set_enabled(false, &HashSet::new()));
+        if let Some(Err(err)) = result {
+            log::error!("failed to disable hooks: {err}");
+            continue;
         }
+
+        if let Ok(mut detours) = detours.try_borrow_mut() {
+            _ = detours.take();
+        }
+    }
+
+    CLIENT_INSTALLED.store(false, Ordering::Relaxed);
+    SERVER_INSTALLED.store(false, Ordering::Relaxed);
+}
+
+/// which set of detours serves a vm
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DetourSet {
+    Client,
+    Server,
+}
+
+impl DetourSet {
+    /// the dll a vm lives in, the ui vm lives in client.dll so it shares the client functions
+    pub fn for_context(context: ScriptContext) -> Self {
+        match context {
+            ScriptContext::SERVER => DetourSet::Server,
+            ScriptContext::CLIENT => DetourSet::Client,
+            ScriptContext::UI => DetourSet::Client,
+        }
+    }
+
+    fn detours(
+        self,
+        engine_token: EngineToken,
+    ) -> &'static RefCell<Option<DetouredSquirrelFunctions>> {
+        match self {
+            DetourSet::Client => CLIENT_DETOURS.get(engine_token),
+            DetourSet::Server => SERVER_DETOURS.get(engine_token),
+        }
+    }
+
+    fn originals(self) -> &'static OnceLock<SquirrelFunctions> {
+        match self {
+            DetourSet::Client => &CLIENT_ORIGINALS,
+            DetourSet::Server => &SERVER_ORIGINALS,
+        }
+    }
+
+    fn installed_flag(self) -> &'static AtomicBool {
+        match self {
+            DetourSet::Client => &CLIENT_INSTALLED,
+            DetourSet::Server => &SERVER_INSTALLED,
+        }
+    }
+
+    pub fn is_installed(self) -> bool {
+        self.installed_flag().load(Ordering::Relaxed)
+    }
+
+    /// for counters kept per set
+    pub const fn index(self) -> usize {
+        match self {
+            DetourSet::Client => 0,
+            DetourSet::Server => 1,
+        }
+    }
+}
+
+/// the dll a hook was installed in, every hook is instantiated once per dll so it always knows whose original to run
+pub trait Dll {
+    const SET: DetourSet;
+}
+
+pub struct ClientDll;
+pub struct ServerDll;
+
+impl Dll for ClientDll {
+    const SET: DetourSet = DetourSet::Client;
+}
+
+impl Dll for ServerDll {
+    const SET: DetourSet = DetourSet::Server;
+}
+
+/// mirrors whether the detour globals are filled for threads without an engine token
+static CLIENT_INSTALLED: AtomicBool = AtomicBool::new(false);
+static SERVER_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// client.dll hosts both the client and ui vm, every other dll has no squirrel vm
+pub fn init_hooks(dll: &WhichDll, engine_token: EngineToken) {
+    match dll {
+        WhichDll::Client => install_hooks::<ClientDll>(SQUIRREL_CLIENT_FUNCS.get(), engine_token),
+        WhichDll::Server => install_hooks::<ServerDll>(SQUIRREL_SERVER_FUNCS.get(), engine_token),
         _ => {}
+    }
+}
+
+fn install_hooks<D: Dll>(funcs: Option<impl Into<SquirrelFunctions>>, engine_token: EngineToken) {
+    let set = D::SET;
+    let diagnostics = &PLUGIN.wait().diagnostics;
+    let Some(funcs) = funcs else {
+        diagnostics.report(format!(
+            "{set:?} squirrel functions were not initialized, skipping its hooks"
+        ));
+        return;
     };
+
+    // saved before anything is patched, the hooks fall back to these
+    let funcs = set.originals().get_or_init(|| funcs.into());
+    let detours = match DetouredSquirrelFunctions::try_new::<D>(funcs) {
+        Ok(detours) => detours,
+        Err(err) => {
+            diagnostics.report(format!("failed to install the {set:?} hooks: {err}"));
+            return;
+        }
+    };
+
+    // in place before anything is enabled so no hook can run without finding them
+    _ = set.detours(engine_token).borrow_mut().replace(detours);
+    set.installed_flag().store(true, Ordering::Relaxed);
+
+    apply_hook_settings(engine_token);
+}
+
+/// resolves the context and reports it when it can't be found
+fn context_of(sqvm: *mut HSquirrelVM) -> Option<ScriptContext> {
+    let context = unsafe { sqvm_to_context(sqvm) };
+    if context.is_none() {
+        PLUGIN
+            .wait()
+            .diagnostics
+            .report("couldn't resolve the context of a sqvm");
+    }
+    context
 }
 
-fn hooks_from_sqvm(sqvm: *mut HSquirrelVM) -> Ref<'static, Option<DetouredSquirrelFunctions>> {
+/// the detours of the dll a hook was installed in
+fn detours<D: Dll>() -> Option<Ref<'static, DetouredSquirrelFunctions>> {
     let engine_token = unsafe { EngineToken::new_unchecked() }; // will only be called in sq function hooks so it's sound
-    match unsafe { sqvm_to_context(sqvm) } {
-        ScriptContext::SERVER => SERVER_DETOURS.get(engine_token).borrow(),
-        _ => CLIENT_DETOURS.get(engine_token).borrow(),
+    let detours = D::SET.detours(engine_token).try_borrow().ok();
+    detours.and_then(|detours| Ref::filter_map(detours, Option::as_ref).ok())
+}
+
+fn report_unhooked(set: DetourSet) {
+    PLUGIN.wait().diagnostics.report(format!(
+        "the {set:?} detours are missing, calling the original function directly"
+    ));
+}
+
+/// runs the original function of the dll `$dll` the hook was installed in
+///
+/// goes through the trampoline while the detours are there, otherwise the function is no longer patched and the saved original is called directly
+///
+/// runs outside of `guarded` so nothing in here may panic, otherwise the original would be skipped
+macro_rules! call_original {
+    ($dll:ty, $detour:ident($($arg:expr),* $(,)?), $default:expr) => {
+        match detours::<$dll>() {
+            Some(detours) => unsafe { detours.$detour.call($($arg),*) },
+            None => match <$dll>::SET.originals().get() {
+                Some(originals) => {
+                    report_unhooked(<$dll>::SET);
+                    unsafe { (originals.$detour)($($arg),*) }
+                }
+                // the hook can't have been installed without saving the originals first
+                None => $default,
+            },
+        }
+    };
+    ($dll:ty, $detour:ident($($arg:expr),* $(,)?)) => {
+        call_original!($dll, $detour($($arg),*), ())
+    };
+}
+
+/// runs the debugger side of a hook, skipped as a whole unless the vm is traced
+///
+/// while nothing is logged or armed this returns before taking a lock, building an event or counting the call
+///
+/// also counts which dll's detours served the vm so the routing shown in the ui is what actually happened
+fn traced<R>(
+    set: DetourSet,
+    hook: &'static str,
+    sqvm: *mut HSquirrelVM,
+    body: impl FnOnce() -> R,
+) -> Option<R> {
+    if !PLUGIN.get()?.is_active() {
+        return None;
     }
+
+    guarded(hook, || {
+        let plugin = PLUGIN.wait();
+        if plugin.is_shutting_down() {
+            return None;
+        }
+
+        let context = context_of(sqvm)?;
+        if hooks_disabled_for(plugin, context) {
+            return None;
+        }
+
+        plugin.debug_info.get(context).hook_calls[set.index()].fetch_add(1, Ordering::Relaxed);
+        if DetourSet::for_context(context) != set {
+            plugin
+                .diagnostics
+                .report(format!("a {context:?} vm called into the {set:?} detours"));
+        }
+
+        Some(body())
+    })
+    .flatten()
 }
 
-fn try_debug(sqvm: *mut HSquirrelVM) {
-    let plugin = PLUGIN.wait();
-    let context = unsafe { sqvm_to_context(sqvm) };
+/// runs a get hook and its original in a single `traced` scope, the fetched value is checked against the data breakpoints
+///
+/// `original` runs exactly once, outside of `traced` if the vm isn't traced or the hook panicked before reaching it
+fn traced_get<R: Copy>(
+    set: DetourSet,
+    hook: &'static str,
+    sqvm: *mut HSquirrelVM,
+    original: impl Fn() -> R,
+    breaks_on: impl FnOnce(R) -> bool,
+) -> R {
+    let mut fetched = None;
+    traced(set, hook, sqvm, || {
+        try_debug(sqvm, hook);
+        let value = original();
+        fetched = Some(value);
+        if breaks_on(value) {
+            try_debug(sqvm, hook);
+        }
+    });
+    fetched.unwrap_or_else(original)
+}
 
-    _ = plugin
-        .send_stack_info
-        .lock()
-        .send((context, StackTrace::DebugBegin(8)));
+/// the vm has its hooks turned off so only the original function should run
+fn hooks_disabled_for(plugin: &DebugPlugin, context: ScriptContext) -> bool {
+    !plugin
+        .debug_info
+        .get(context)
+        .hooks_enabled
+        .load(Ordering::Relaxed)
+}
 
-    match context {
-        ScriptContext::SERVER if *plugin.debug_info.server.paused.lock() => {
-            _ = plugin.debug_info.server.unpause_waiter.lock().recv()
+/// the index of the entity behind an entity object, resolved the same way northstar's `getentity` does
+///
+/// goes through the trampoline so looking at an argument can't end up in our own hook
+pub(crate) fn entity_index(sqvm: *mut HSquirrelVM, object: &SQObject) -> Option<u32> {
+    let engine_token = unsafe { EngineToken::new_unchecked() }; // only called from hooks and sq functions
+    let set = DetourSet::for_context(unsafe { sqvm_to_context(sqvm) }?);
+    let entity_constant = match set {
+        DetourSet::Client => {
+            SQUIRREL_CLIENT_FUNCS
+                .get()?
+                .sq_get_entity_constant_cbase_entity
         }
-        ScriptContext::CLIENT if *plugin.debug_info.client.paused.lock() => {
-            _ = plugin.debug_info.client.unpause_waiter.lock().recv()
+        DetourSet::Server => {
+            SQUIRREL_SERVER_FUNCS
+                .get()?
+                .sq_get_entity_constant_cbase_entity
         }
-        ScriptContext::UI if *plugin.debug_info.ui.paused.lock() => {
-            _ = plugin.debug_info.ui.unpause_waiter.lock().recv()
+    };
+    let csqvm = unsafe { sqvm.as_ref()?.sharedState.as_ref()?.cSquirrelVM };
+
+    let detours = set.detours(engine_token).try_borrow().ok()?;
+    let mut object = *object;
+    let entity = unsafe {
+        detours
+            .as_ref()?
+            .sq_getentityfrominstance
+            .call(csqvm, &mut object, entity_constant())
+    };
+
+    // `CPlayer` is the only entity layout rrplug knows, the index sits at the same offset on every entity
+    unsafe { entity.as_ref() }.map(|entity| entity.player_index)
+}
+
+fn csqvm_to_sqvm(sqvm: *mut CSquirrelVM) -> *mut HSquirrelVM {
+    unsafe { sqvm.as_ref() }
+        .map(|sqvm| sqvm.sqvm)
+        .unwrap_or(std::ptr::null_mut())
+}
+
+fn string_from_ptr(ptr: *const SQChar) -> String {
+    if ptr.is_null() {
+        return "<null>".to_string();
+    }
+    from_char_ptr(ptr)
+}
+
+/// the queue the hooks of this thread push their events into
+fn event_queue(plugin: &DebugPlugin) -> &EventQueue {
+    #[cfg(feature = "bench")]
+    if let Some(events) = BENCH_EVENTS.with(Cell::get) {
+        return events;
+    }
+    &plugin.events
+}
+
+pub(crate) fn try_debug(sqvm: *mut HSquirrelVM, hook: &'static str) {
+    let plugin = PLUGIN.wait();
+    if plugin.is_shutting_down() {
+        return;
+    }
+
+    let Some(context) = context_of(sqvm) else {
+        return;
+    };
+    if hooks_disabled_for(plugin, context) {
+        return;
+    }
+
+    if plugin.settings.logging.load(Ordering::Relaxed) {
+        event_queue(plugin).push(context, StackTrace::DebugBegin(8));
+    }
+
+    let state = plugin.debug_info.get(context);
+    let location = Location {
+        hook,
+        depth: state.call_depth.load(Ordering::Relaxed),
+    };
+    let stop = state.checkpoint(location, |reason, location| {
+        event_queue(plugin).push(
+            context,
+            StackTrace::Misc(format!("paused in {location}: {reason}")),
+        );
+    });
+
+    if let Some(Stop {
+        reason,
+        timed_out: Some(timed_out),
+    }) = stop
+    {
+        let warning = format!("resumed on its own after being paused for {timed_out:?} ({reason})");
+        log::warn!("[{context:?}] {warning}");
+        event_queue(plugin).push(context, StackTrace::Misc(warning));
+    }
+}
+
+/// pauses before a script matching a load breakpoint is compiled
+///
+/// only called inside `traced` which already leaves out poisoned hooks, the vm is checked here again anyway
+fn break_on_load(sqvm: *mut HSquirrelVM, file: *const SQChar) {
+    let plugin = PLUGIN.wait();
+    let Some(context) = context_of(sqvm) else {
+        return;
+    };
+    if hooks_disabled_for(plugin, context) {
+        return;
+    }
+
+    let state = plugin.debug_info.get(context);
+    if let Some(reason) = state.breaks_on_load(&string_from_ptr(file)) {
+        state.pause(reason);
+    }
+}
+
+/// pauses when a pushed or fetched value matches a data breakpoint, returns whether it did
+///
+/// `position` is `None` for pushes since the value is about to land on the top of the stack
+fn break_on_value(
+    sqvm: *mut HSquirrelVM,
+    hook: &'static str,
+    position: Option<SQInteger>,
+    value: impl FnOnce() -> DataValue,
+) -> bool {
+    let plugin = PLUGIN.wait();
+    let Some(context) = context_of(sqvm) else {
+        return false;
+    };
+    if hooks_disabled_for(plugin, context) {
+        return false;
+    }
+
+    let state = plugin.debug_info.get(context);
+    if !state.has_data_breakpoints() {
+        return false;
+    }
+
+    let position = position.unwrap_or_else(|| {
+        unsafe { sqvm.as_ref() }
+            .map(|sqvm| sqvm._top - sqvm._stackbase + 1)
+            .unwrap_or_default()
+    });
+    match state.breaks_on_value(hook, position as i64, &value()) {
+        Some(reason) => {
+            state.pause(reason);
+            true
         }
-        _ => {}
+        None => false,
+    }
+}
+
+pub(crate) fn push_log(sqvm: *mut HSquirrelVM, log: StackTrace) {
+    push_log_with(sqvm, || log)
+}
+
+/// only builds the event if it's going to be recorded
+///
+/// the filters are applied by whoever drains the queue so the vm threads never wait on them
+pub(crate) fn push_log_with(sqvm: *mut HSquirrelVM, log: impl FnOnce() -> StackTrace) {
+    let plugin = PLUGIN.wait();
+    if !logging_enabled() || plugin.is_shutting_down() {
+        return;
+    }
+
+    let Some(context) = context_of(sqvm) else {
+        return;
+    };
+    if hooks_disabled_for(plugin, context) || plugin.debug_info.get(context).hides_trace() {
+        return;
     }
+
+    event_queue(plugin).push(context, log());
 }
 
-fn push_log(sqvm: *mut HSquirrelVM, log: StackTrace) {
-    _ = PLUGIN
-        .wait()
-        .send_stack_info
-        .lock()
-        .send((unsafe { sqvm_to_context(sqvm) }, log));
+pub(crate) fn logging_enabled() -> bool {
+    PLUGIN.wait().settings.logging.load(Ordering::Relaxed)
 }
 
-pub unsafe extern "C" fn hook_sq_defconst(
+pub unsafe extern "C" fn hook_sq_defconst<D: Dll>(
     sqvm: *mut CSquirrelVM,
     name: *const SQChar,
     value: ::std::os::raw::c_int,
 ) {
-    let cssqvm = unsafe { (*sqvm).sqvm };
-    try_debug(cssqvm);
-    hooks_from_sqvm(cssqvm)
-        .as_ref()
-        .unwrap()
-        .sq_defconst
-        .call(sqvm, name, value);
-}
-pub extern "C" fn hook_sq_compilebuffer(
+    let cssqvm = csqvm_to_sqvm(sqvm);
+    traced(D::SET, "sq_defconst", cssqvm, || {
+        try_debug(cssqvm, "sq_defconst")
+    });
+    call_original!(D, sq_defconst(sqvm, name, value))
+}
+pub extern "C" fn hook_sq_compilebuffer<D: Dll>(
     sqvm: *mut HSquirrelVM,
     compile_buffer: *mut CompileBufferState,
     file: *const ::std::os::raw::c_char,
     a1: ::std::os::raw::c_int,
     should_throw_error: SQBool,
 ) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_compilebuffer
-            .call(sqvm, compile_buffer, file, a1, should_throw_error)
-    }
+    traced(D::SET, "sq_compilebuffer", sqvm, || {
+        if !COMPILING_FILE.with(Cell::get) {
+            break_on_load(sqvm, file);
+        }
+        try_debug(sqvm, "sq_compilebuffer");
+    });
+    call_original!(
+        D,
+        sq_compilebuffer(sqvm, compile_buffer, file, a1, should_throw_error),
+        SQRESULT::SQRESULT_ERROR
+    )
 }
-pub unsafe extern "C" fn hook_sq_call(
+pub unsafe extern "C" fn hook_sq_call<D: Dll>(
     sqvm: *mut HSquirrelVM,
     args: SQInteger,
     should_return: SQBool,
     throw_error: SQBool,
 ) -> SQRESULT {
-    push_log(sqvm, StackTrace::func_called(sqvm));
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_call
-            .call(sqvm, args, should_return, throw_error)
+    let call = traced(D::SET, "sq_call", sqvm, || {
+        let state = PLUGIN.wait().debug_info.get(context_of(sqvm)?);
+        // taken back once the call returned, the unwinding of a panic in here drops it as well
+        let mut call = state.enter_traced_call();
+        let depth = call.depth();
+
+        let watched = watchdog_enabled();
+        let mut called = None;
+        if watched || state.has_breakpoints() || state.has_skips() {
+            let sqvm_ref = unsafe { sqvm.as_ref() };
+            let name = sqvm_ref
+                .map(|sqvm| FunctionName::called(sqvm, args))
+                .unwrap_or(FunctionName::Unknown);
+            let file = (state.has_skips() || state.has_first_call_breakpoints())
+                .then(|| sqvm_ref.and_then(|sqvm| FunctionName::called_file(sqvm, args)))
+                .flatten();
+            if state.has_skips() {
+                call.enter_skipped(name.as_str(), file.as_deref());
+            }
+            if let Some(reason) = state.breaks_on_call(&name, file.as_deref()) {
+                state.pause(reason);
+            }
+            called = Some(name);
+        }
+        push_log_with(sqvm, || StackTrace::func_called(sqvm, args, depth));
+        try_debug(sqvm, "sq_call");
+
+        // entered once a pause right at this call is over so the watchdog and the call tree only time the call itself
+        if let Some(name) = called.filter(|_| watched) {
+            call.enter_call(name);
+        }
+        call.start_timing();
+
+        Some(call)
+    })
+    .flatten();
+
+    let result = call_original!(
+        D,
+        sq_call(sqvm, args, should_return, throw_error),
+        SQRESULT::SQRESULT_ERROR
+    );
+
+    if let Some(call) = call {
+        let depth = call.depth();
+        let elapsed = call.elapsed();
+        guarded("sq_call", || {
+            push_log_with(sqvm, || StackTrace::Return { depth, elapsed });
+        });
+
+        // after the return was logged so it's hidden together with the call, outside of `guarded` so a panic can't skip it
+        drop(call);
     }
+
+    result
 }
-pub unsafe extern "C" fn hook_sq_raiseerror(
+pub unsafe extern "C" fn hook_sq_raiseerror<D: Dll>(
     sqvm: *mut HSquirrelVM,
     error: *const SQChar,
 ) -> SQInteger {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_raiseerror
-            .call(sqvm, error)
-    }
+    traced(D::SET, "sq_raiseerror", sqvm, || {
+        let plugin = PLUGIN.wait();
+        if plugin.settings.break_on_error.load(Ordering::Relaxed) {
+            let error = string_from_ptr(error);
+            push_log_with(sqvm, || StackTrace::Misc(format!("error: {error}")));
+            if let Some(context) = context_of(sqvm) {
+                plugin
+                    .debug_info
+                    .get(context)
+                    .pause(PauseReason::Error(error));
+            }
+        }
+        try_debug(sqvm, "sq_raiseerror");
+    });
+    call_original!(D, sq_raiseerror(sqvm, error), Default::default())
 }
-pub unsafe extern "C" fn hook_sq_compilefile(
+pub unsafe extern "C" fn hook_sq_compilefile<D: Dll>(
     sqvm: *mut CSquirrelVM,
     path: *const ::std::os::raw::c_char,
     name: *const ::std::os::raw::c_char,
     a4: ::std::os::raw::c_int,
 ) -> bool {
-    let cssqvm = unsafe { (*sqvm).sqvm };
-    try_debug(cssqvm);
-    unsafe {
-        hooks_from_sqvm(cssqvm)
-            .as_ref()
-            .unwrap()
-            .sq_compilefile
-            .call(sqvm, path, name, a4)
-    }
+    let cssqvm = csqvm_to_sqvm(sqvm);
+    traced(D::SET, "sq_compilefile", cssqvm, || {
+        break_on_load(cssqvm, path);
+        try_debug(cssqvm, "sq_compilefile");
+    });
+
+    let was_compiling = COMPILING_FILE.with(|compiling| compiling.replace(true));
+    let result = call_original!(D, sq_compilefile(sqvm, path, name, a4), Default::default());
+    COMPILING_FILE.with(|compiling| compiling.set(was_compiling));
+    result
 }
-pub unsafe extern "C" fn hook_sq_newarray(sqvm: *mut HSquirrelVM, stackpos: SQInteger) {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_newarray
-            .call(sqvm, stackpos)
-    }
+pub unsafe extern "C" fn hook_sq_newarray<D: Dll>(sqvm: *mut HSquirrelVM, stackpos: SQInteger) {
+    traced(D::SET, "sq_newarray", sqvm, || {
+        try_debug(sqvm, "sq_newarray")
+    });
+    call_original!(D, sq_newarray(sqvm, stackpos))
 }
-pub unsafe extern "C" fn hook_sq_arrayappend(
+pub unsafe extern "C" fn hook_sq_arrayappend<D: Dll>(
     sqvm: *mut HSquirrelVM,
     stackpos: SQInteger,
 ) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_arrayappend
-            .call(sqvm, stackpos)
-    }
+    traced(D::SET, "sq_arrayappend", sqvm, || {
+        try_debug(sqvm, "sq_arrayappend")
+    });
+    call_original!(D, sq_arrayappend(sqvm, stackpos), SQRESULT::SQRESULT_ERROR)
 }
-pub unsafe extern "C" fn hook_sq_newtable(sqvm: *mut HSquirrelVM) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_newtable
-            .call(sqvm)
-    }
+pub unsafe extern "C" fn hook_sq_newtable<D: Dll>(sqvm: *mut HSquirrelVM) -> SQRESULT {
+    traced(D::SET, "sq_newtable", sqvm, || {
+        try_debug(sqvm, "sq_newtable")
+    });
+    call_original!(D, sq_newtable(sqvm), SQRESULT::SQRESULT_ERROR)
 }
-pub unsafe extern "C" fn hook_sq_newslot(
+pub unsafe extern "C" fn hook_sq_newslot<D: Dll>(
     sqvm: *mut HSquirrelVM,
     idx: SQInteger,
     _static: SQBool,
 ) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_newslot
-            .call(sqvm, idx, _static)
-    }
+    traced(D::SET, "sq_newslot", sqvm, || try_debug(sqvm, "sq_newslot"));
+    call_original!(D, sq_newslot(sqvm, idx, _static), SQRESULT::SQRESULT_ERROR)
 }
-pub unsafe extern "C" fn hook_sq_pushroottable(sqvm: *mut HSquirrelVM) {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushroottable
-            .call(sqvm)
-    }
+pub unsafe extern "C" fn hook_sq_pushroottable<D: Dll>(sqvm: *mut HSquirrelVM) {
+    traced(D::SET, "sq_pushroottable", sqvm, || {
+        try_debug(sqvm, "sq_pushroottable")
+    });
+    call_original!(D, sq_pushroottable(sqvm))
 }
-pub unsafe extern "C" fn hook_sq_pushstring(
+pub unsafe extern "C" fn hook_sq_pushstring<D: Dll>(
     sqvm: *mut HSquirrelVM,
     str: *const SQChar,
     length: SQInteger,
 ) {
-    push_log(sqvm, StackTrace::value_pushed(from_char_ptr::<String>(str)));
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushstring
-            .call(sqvm, str, length)
-    }
+    traced(D::SET, "sq_pushstring", sqvm, || {
+        push_log_with(sqvm, || StackTrace::value_pushed(TraceValue::from_ptr(str)));
+        break_on_value(sqvm, "sq_pushstring", None, || {
+            DataValue::String(string_from_ptr(str))
+        });
+        try_debug(sqvm, "sq_pushstring");
+    });
+    call_original!(D, sq_pushstring(sqvm, str, length))
 }
-pub unsafe extern "C" fn hook_sq_pushinteger(sqvm: *mut HSquirrelVM, i: SQInteger) {
-    push_log(sqvm, StackTrace::value_pushed(i));
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushinteger
-            .call(sqvm, i)
-    }
+pub unsafe extern "C" fn hook_sq_pushinteger<D: Dll>(sqvm: *mut HSquirrelVM, i: SQInteger) {
+    trace_pushinteger(D::SET, sqvm, i);
+    call_original!(D, sq_pushinteger(sqvm, i))
 }
-pub unsafe extern "C" fn hook_sq_pushfloat(sqvm: *mut HSquirrelVM, f: SQFloat) {
-    push_log(sqvm, StackTrace::value_pushed(f));
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushfloat
-            .call(sqvm, f)
-    }
+/// the debugger side of `sq_pushinteger` on its own so the bench runs exactly what the hook does
+pub(crate) fn trace_pushinteger(set: DetourSet, sqvm: *mut HSquirrelVM, i: SQInteger) {
+    traced(set, "sq_pushinteger", sqvm, || {
+        push_log_with(sqvm, || StackTrace::value_pushed(i));
+        break_on_value(sqvm, "sq_pushinteger", None, || {
+            DataValue::Integer(i as i64)
+        });
+        try_debug(sqvm, "sq_pushinteger");
+    });
 }
-pub unsafe extern "C" fn hook_sq_pushbool(sqvm: *mut HSquirrelVM, b: SQBool) {
-    push_log(sqvm, StackTrace::value_pushed(b));
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushbool
-            .call(sqvm, b)
-    }
+pub unsafe extern "C" fn hook_sq_pushfloat<D: Dll>(sqvm: *mut HSquirrelVM, f: SQFloat) {
+    traced(D::SET, "sq_pushfloat", sqvm, || {
+        push_log_with(sqvm, || StackTrace::value_pushed(f));
+        break_on_value(sqvm, "sq_pushfloat", None, || DataValue::Float(f));
+        try_debug(sqvm, "sq_pushfloat");
+    });
+    call_original!(D, sq_pushfloat(sqvm, f))
+}
+pub unsafe extern "C" fn hook_sq_pushbool<D: Dll>(sqvm: *mut HSquirrelVM, b: SQBool) {
+    traced(D::SET, "sq_pushbool", sqvm, || {
+        push_log_with(sqvm, || StackTrace::value_pushed(b != 0));
+        break_on_value(sqvm, "sq_pushbool", None, || DataValue::Bool(b != 0));
+        try_debug(sqvm, "sq_pushbool");
+    });
+    call_original!(D, sq_pushbool(sqvm, b))
 }
-pub unsafe extern "C" fn hook_sq_pushasset(
+pub unsafe extern "C" fn hook_sq_pushasset<D: Dll>(
     sqvm: *mut HSquirrelVM,
     str: *const SQChar,
     length: SQInteger,
 ) {
-    push_log(sqvm, StackTrace::value_pushed(from_char_ptr::<String>(str)));
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushasset
-            .call(sqvm, str, length)
-    }
+    traced(D::SET, "sq_pushasset", sqvm, || {
+        push_log_with(sqvm, || StackTrace::value_pushed(TraceValue::from_ptr(str)));
+        break_on_value(sqvm, "sq_pushasset", None, || {
+            DataValue::String(string_from_ptr(str))
+        });
+        try_debug(sqvm, "sq_pushasset");
+    });
+    call_original!(D, sq_pushasset(sqvm, str, length))
 }
-pub unsafe extern "C" fn hook_sq_pushvector(sqvm: *mut HSquirrelVM, vec: *const SQFloat) {
-    push_log(
-        sqvm,
-        StackTrace::value_pushed(format!("{:?}", Vector3::from(vec.cast()))),
-    );
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushvector
-            .call(sqvm, vec)
-    }
+pub unsafe extern "C" fn hook_sq_pushvector<D: Dll>(sqvm: *mut HSquirrelVM, vec: *const SQFloat) {
+    traced(D::SET, "sq_pushvector", sqvm, || {
+        push_log_with(sqvm, || {
+            if vec.is_null() {
+                StackTrace::value_pushed(TraceValue::Null)
+            } else {
+                StackTrace::value_pushed(TraceValue::Vector(unsafe {
+                    [*vec, *vec.add(1), *vec.add(2)]
+                }))
+            }
+        });
+        try_debug(sqvm, "sq_pushvector");
+    });
+    call_original!(D, sq_pushvector(sqvm, vec))
 }
-pub unsafe extern "C" fn hook_sq_pushobject(sqvm: *mut HSquirrelVM, obj: *mut SQObject) {
-    push_log(sqvm, StackTrace::value_pushed("A Object"));
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushobject
-            .call(sqvm, obj)
-    }
+pub unsafe extern "C" fn hook_sq_pushobject<D: Dll>(sqvm: *mut HSquirrelVM, obj: *mut SQObject) {
+    traced(D::SET, "sq_pushobject", sqvm, || {
+        push_log_with(sqvm, || {
+            StackTrace::value_pushed(match unsafe { obj.as_ref() } {
+                Some(obj) => unsafe { TraceValue::from_object(sqvm, obj) },
+                None => TraceValue::Null,
+            })
+        });
+        try_debug(sqvm, "sq_pushobject");
+    });
+    call_original!(D, sq_pushobject(sqvm, obj))
 }
-pub unsafe extern "C" fn hook_sq_getstring(
+pub unsafe extern "C" fn hook_sq_getstring<D: Dll>(
     sqvm: *mut HSquirrelVM,
     stackpos: SQInteger,
 ) -> *const SQChar {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getstring
-            .call(sqvm, stackpos)
-    }
+    traced_get(
+        D::SET,
+        "sq_getstring",
+        sqvm,
+        || call_original!(D, sq_getstring(sqvm, stackpos), std::ptr::null()),
+        |value| {
+            break_on_value(sqvm, "sq_getstring", Some(stackpos), || {
+                DataValue::String(string_from_ptr(value))
+            })
+        },
+    )
 }
-pub unsafe extern "C" fn hook_sq_getinteger(
+pub unsafe extern "C" fn hook_sq_getinteger<D: Dll>(
     sqvm: *mut HSquirrelVM,
     stackpos: SQInteger,
 ) -> SQInteger {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getinteger
-            .call(sqvm, stackpos)
-    }
+    traced_get(
+        D::SET,
+        "sq_getinteger",
+        sqvm,
+        || call_original!(D, sq_getinteger(sqvm, stackpos), Default::default()),
+        |value| {
+            break_on_value(sqvm, "sq_getinteger", Some(stackpos), || {
+                DataValue::Integer(value as i64)
+            })
+        },
+    )
 }
-pub unsafe extern "C" fn hook_sq_getfloat(sqvm: *mut HSquirrelVM, stackpos: SQInteger) -> SQFloat {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getfloat
-            .call(sqvm, stackpos)
-    }
+pub unsafe extern "C" fn hook_sq_getfloat<D: Dll>(
+    sqvm: *mut HSquirrelVM,
+    stackpos: SQInteger,
+) -> SQFloat {
+    traced_get(
+        D::SET,
+        "sq_getfloat",
+        sqvm,
+        || call_original!(D, sq_getfloat(sqvm, stackpos), Default::default()),
+        |value| {
+            break_on_value(sqvm, "sq_getfloat", Some(stackpos), || {
+                DataValue::Float(value)
+            })
+        },
+    )
 }
-pub unsafe extern "C" fn hook_sq_getbool(sqvm: *mut HSquirrelVM, stackpos: SQInteger) -> SQBool {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getbool
-            .call(sqvm, stackpos)
-    }
+pub unsafe extern "C" fn hook_sq_getbool<D: Dll>(
+    sqvm: *mut HSquirrelVM,
+    stackpos: SQInteger,
+) -> SQBool {
+    traced_get(
+        D::SET,
+        "sq_getbool",
+        sqvm,
+        || call_original!(D, sq_getbool(sqvm, stackpos), Default::default()),
+        |value| {
+            break_on_value(sqvm, "sq_getbool", Some(stackpos), || {
+                DataValue::Bool(value != 0)
+            })
+        },
+    )
 }
-pub unsafe extern "C" fn hook_sq_get(sqvm: *mut HSquirrelVM, stackpos: SQInteger) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_get
-            .call(sqvm, stackpos)
-    }
+pub unsafe extern "C" fn hook_sq_get<D: Dll>(
+    sqvm: *mut HSquirrelVM,
+    stackpos: SQInteger,
+) -> SQRESULT {
+    traced(D::SET, "sq_get", sqvm, || try_debug(sqvm, "sq_get"));
+    call_original!(D, sq_get(sqvm, stackpos), SQRESULT::SQRESULT_ERROR)
 }
-pub unsafe extern "C" fn hook_sq_getasset(
+pub unsafe extern "C" fn hook_sq_getasset<D: Dll>(
     sqvm: *mut HSquirrelVM,
     stackpos: SQInteger,
     result: *mut *const ::std::os::raw::c_char,
 ) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getasset
-            .call(sqvm, stackpos, result)
-    }
+    traced_get(
+        D::SET,
+        "sq_getasset",
+        sqvm,
+        || {
+            call_original!(
+                D,
+                sq_getasset(sqvm, stackpos, result),
+                SQRESULT::SQRESULT_ERROR
+            )
+        },
+        |sqresult| {
+            !matches!(sqresult, SQRESULT::SQRESULT_ERROR)
+                && !result.is_null()
+                && break_on_value(sqvm, "sq_getasset", Some(stackpos), || {
+                    DataValue::String(string_from_ptr(unsafe { *result }))
+                })
+        },
+    )
 }
-pub unsafe extern "C" fn hook_sq_getuserdata(
+pub unsafe extern "C" fn hook_sq_getuserdata<D: Dll>(
     sqvm: *mut HSquirrelVM,
     stackpos: SQInteger,
     data: *mut *mut ::std::os::raw::c_void,
     type_id: *mut u64,
 ) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getuserdata
-            .call(sqvm, stackpos, data, type_id)
-    }
+    traced(D::SET, "sq_getuserdata", sqvm, || {
+        try_debug(sqvm, "sq_getuserdata")
+    });
+    call_original!(
+        D,
+        sq_getuserdata(sqvm, stackpos, data, type_id),
+        SQRESULT::SQRESULT_ERROR
+    )
 }
-pub unsafe extern "C" fn hook_sq_getvector(
+pub unsafe extern "C" fn hook_sq_getvector<D: Dll>(
     sqvm: *mut HSquirrelVM,
     stackpos: SQInteger,
 ) -> *mut SQFloat {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getvector
-            .call(sqvm, stackpos)
-    }
+    traced(D::SET, "sq_getvector", sqvm, || {
+        try_debug(sqvm, "sq_getvector")
+    });
+    call_original!(D, sq_getvector(sqvm, stackpos), std::ptr::null_mut())
 }
-pub unsafe extern "C" fn hook_sq_getthisentity(
+pub unsafe extern "C" fn hook_sq_getthisentity<D: Dll>(
     sqvm: *mut HSquirrelVM,
     entity: *mut *mut ::std::os::raw::c_void,
 ) -> SQBool {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getthisentity
-            .call(sqvm, entity)
-    }
+    traced(D::SET, "sq_getthisentity", sqvm, || {
+        try_debug(sqvm, "sq_getthisentity")
+    });
+    call_original!(D, sq_getthisentity(sqvm, entity), Default::default())
 }
-pub unsafe extern "C" fn hook_sq_getobject(
+pub unsafe extern "C" fn hook_sq_getobject<D: Dll>(
     sqvm: *mut HSquirrelVM,
     stack_pos: SQInteger,
     out_obj: *mut SQObject,
 ) {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getobject
-            .call(sqvm, stack_pos, out_obj)
-    }
+    traced(D::SET, "sq_getobject", sqvm, || {
+        try_debug(sqvm, "sq_getobject")
+    });
+    call_original!(D, sq_getobject(sqvm, stack_pos, out_obj))
 }
-pub unsafe extern "C" fn hook_sq_stackinfos(
+pub unsafe extern "C" fn hook_sq_stackinfos<D: Dll>(
     sqvm: *mut HSquirrelVM,
     level: ::std::os::raw::c_int,
     out_obj: *mut SQStackInfos,
     call_stack_size: ::std::os::raw::c_int,
 ) -> ::std::os::raw::c_longlong {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm).as_ref().unwrap().sq_stackinfos.call(
-            sqvm,
-            level,
-            out_obj,
-            call_stack_size,
-        )
-    }
+    traced(D::SET, "sq_stackinfos", sqvm, || {
+        try_debug(sqvm, "sq_stackinfos")
+    });
+    call_original!(
+        D,
+        sq_stackinfos(sqvm, level, out_obj, call_stack_size),
+        Default::default()
+    )
 }
-pub unsafe extern "C" fn hook_sq_createuserdata(
+pub unsafe extern "C" fn hook_sq_createuserdata<D: Dll>(
     sqvm: *mut HSquirrelVM,
     size: SQInteger,
 ) -> *mut ::std::os::raw::c_void {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_createuserdata
-            .call(sqvm, size)
-    }
+    traced(D::SET, "sq_createuserdata", sqvm, || {
+        try_debug(sqvm, "sq_createuserdata")
+    });
+    call_original!(D, sq_createuserdata(sqvm, size), std::ptr::null_mut())
 }
-pub unsafe extern "C" fn hook_sq_setuserdatatypeid(
+pub unsafe extern "C" fn hook_sq_setuserdatatypeid<D: Dll>(
     sqvm: *mut HSquirrelVM,
     stackpos: SQInteger,
     type_id: u64,
 ) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_setuserdatatypeid
-            .call(sqvm, stackpos, type_id)
-    }
+    traced(D::SET, "sq_setuserdatatypeid", sqvm, || {
+        try_debug(sqvm, "sq_setuserdatatypeid")
+    });
+    call_original!(
+        D,
+        sq_setuserdatatypeid(sqvm, stackpos, type_id),
+        SQRESULT::SQRESULT_ERROR
+    )
 }
-pub unsafe extern "C" fn hook_sq_getentityfrominstance(
+pub unsafe extern "C" fn hook_sq_getentityfrominstance<D: Dll>(
     sqvm: *mut CSquirrelVM,
     instance: *mut SQObject,
     entity_constant: *mut *mut ::std::os::raw::c_char,
 ) -> *mut CPlayer {
-    let hssqvm = unsafe { (*sqvm).sqvm };
-    try_debug(hssqvm);
-    unsafe {
-        hooks_from_sqvm(hssqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getentityfrominstance
-            .call(sqvm, instance, entity_constant)
-    }
+    let hssqvm = csqvm_to_sqvm(sqvm);
+    traced(D::SET, "sq_getentityfrominstance", hssqvm, || {
+        try_debug(hssqvm, "sq_getentityfrominstance")
+    });
+    call_original!(
+        D,
+        sq_getentityfrominstance(sqvm, instance, entity_constant),
+        std::ptr::null_mut()
+    )
 }
-pub unsafe extern "C" fn hook_sq_getfunction(
+pub unsafe extern "C" fn hook_sq_getfunction<D: Dll>(
     sqvm: *mut HSquirrelVM,
     name: *const ::std::os::raw::c_char,
     return_obj: *mut SQObject,
     signature: *const ::std::os::raw::c_char,
 ) -> ::std::os::raw::c_int {
-    push_log(
-        sqvm,
-        StackTrace::value_gotten(from_char_ptr::<String>(name)),
-    );
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_getfunction
-            .call(sqvm, name, return_obj, signature)
-    }
+    traced(D::SET, "sq_getfunction", sqvm, || {
+        push_log_with(sqvm, || {
+            StackTrace::value_gotten(TraceValue::from_ptr(name))
+        });
+        try_debug(sqvm, "sq_getfunction");
+    });
+    call_original!(
+        D,
+        sq_getfunction(sqvm, name, return_obj, signature),
+        Default::default()
+    )
 }
-pub unsafe extern "C" fn hook_sq_pushnewstructinstance(
+pub unsafe extern "C" fn hook_sq_pushnewstructinstance<D: Dll>(
     sqvm: *mut HSquirrelVM,
     field_count: ::std::os::raw::c_int,
 ) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_pushnewstructinstance
-            .call(sqvm, field_count)
-    }
+    traced(D::SET, "sq_pushnewstructinstance", sqvm, || {
+        try_debug(sqvm, "sq_pushnewstructinstance")
+    });
+    call_original!(
+        D,
+        sq_pushnewstructinstance(sqvm, field_count),
+        SQRESULT::SQRESULT_ERROR
+    )
 }
-pub unsafe extern "C" fn hook_sq_sealstructslot(
+pub unsafe extern "C" fn hook_sq_sealstructslot<D: Dll>(
     sqvm: *mut HSquirrelVM,
     slot_index: ::std::os::raw::c_int,
 ) -> SQRESULT {
-    try_debug(sqvm);
-    unsafe {
-        hooks_from_sqvm(sqvm)
-            .as_ref()
-            .unwrap()
-            .sq_sealstructslot
-            .call(sqvm, slot_index)
-    }
+    traced(D::SET, "sq_sealstructslot", sqvm, || {
+        try_debug(sqvm, "sq_sealstructslot")
+    });
+    call_original!(
+        D,
+        sq_sealstructslot(sqvm, slot_index),
+        SQRESULT::SQRESULT_ERROR
+    )
 }
@@ -1,16 +1,60 @@
 use dbgview::init_window;
-use debug::DebugState;
+use debug::{DebugState, PauseReason};
+use diagnostics::Diagnostics;
 use parking_lot::Mutex;
 use rrplug::prelude::*;
 use stacktrace::StackTrace;
-use std::sync::mpsc::{self, Sender};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
-use crate::hooks::init_hooks;
+use crate::{
+    commands::register_commands,
+    config::{config_path, Config},
+    convars::{engine_network_timeout_ms, register_convars, Settings},
+    crashdump::{install_exception_handler, uninstall_exception_handler},
+    events::EventQueue,
+    guard::install_panic_hook,
+    hooks::{apply_hook_settings, init_hooks, teardown_hooks},
+    recording::Recording,
+    session::{restore_session, SessionState},
+    sqapi::register_sq_api,
+};
 
+#[cfg(feature = "bench")]
+mod bench;
+mod calltree;
+mod commands;
+mod config;
+mod convars;
+mod crashdump;
+mod databreak;
 mod dbgview;
 mod debug;
+mod diagnostics;
+mod events;
+mod guard;
+mod headless;
 mod hooks;
+mod recording;
+mod session;
+mod sqapi;
 mod stacktrace;
+mod watchdog;
+
+/// also the order "run all" and "step all" release them in, the server first so it can answer the client
+pub const ALL_CONTEXTS: [ScriptContext; 3] = [
+    ScriptContext::SERVER,
+    ScriptContext::CLIENT,
+    ScriptContext::UI,
+];
+
+/// how long releasing every vm waits on each one before it moves on to the next
+const RELEASE_TIMEOUT: Duration = Duration::from_millis(100);
+/// how long `shutdown` waits for our threads to notice
+const THREAD_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct VmSpecific<T> {
     server: T,
@@ -51,8 +95,19 @@ impl<T: Default> Default for VmSpecific<T> {
 }
 
 pub struct DebugPlugin {
-    pub(crate) send_stack_info: Mutex<Sender<(ScriptContext, StackTrace)>>,
+    pub(crate) events: EventQueue,
     pub(crate) debug_info: VmSpecific<DebugState>,
+    pub(crate) recording: Recording,
+    pub(crate) settings: Settings,
+    /// whether a window or a remote client is looking at the trace
+    pub(crate) attached: AtomicBool,
+    pub(crate) session: Mutex<SessionState>,
+    pub(crate) shutting_down: AtomicBool,
+    /// the window or headless thread and the watchdog
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    /// set for a reloaded copy, it registers and hooks nothing since the engine still holds what the unloaded one registered
+    inert: bool,
+    pub(crate) diagnostics: Diagnostics,
 }
 
 impl Plugin for DebugPlugin {
@@ -63,50 +118,254 @@ impl Plugin for DebugPlugin {
         PluginContext::all(), // context -> if it has only client it will not load on dedicated servers
     );
 
-    fn new(_reloaded: bool) -> Self {
-        let (send, recv) = mpsc::channel();
+    fn new(reloaded: bool) -> Self {
+        // the engine can't forget the concommands, convars and squirrel functions of the unloaded copy,
+        // they point into freed code and registering them again would collide with them
+        let inert = reloaded;
+        if inert {
+            log::error!("dbgquirrel can't be reloaded, it stays off until the game is restarted");
+        } else {
+            install_panic_hook();
+            install_exception_handler();
+            register_sq_api();
+        }
 
-        std::thread::spawn(move || init_window(recv));
+        let config = Config::load(&config_path()).unwrap_or_else(|err| {
+            log::error!("{err}; falling back to the default config");
+            Config::default()
+        });
 
-        Self {
-            send_stack_info: send.into(),
+        let threads = if inert {
+            Vec::new()
+        } else {
+            let ui_thread = if headless::is_headless(&config.ui) {
+                std::thread::spawn(headless::run)
+            } else {
+                let ui_config = config.ui.clone();
+                std::thread::spawn(move || init_window(ui_config))
+            };
+            vec![ui_thread, std::thread::spawn(watchdog::run)]
+        };
+
+        let plugin = Self {
+            events: EventQueue::default(),
             debug_info: VmSpecific::new(),
+            recording: Recording::default(),
+            settings: Settings::default(),
+            attached: AtomicBool::new(false),
+            session: Mutex::new(SessionState::default()),
+            shutting_down: AtomicBool::new(false),
+            threads: Mutex::new(threads),
+            inert,
+            diagnostics: Diagnostics::default(),
+        };
+        if !inert {
+            restore_session(&plugin);
+            config.apply(&plugin);
         }
+
+        plugin
     }
 
     fn on_dll_load(
         &self,
-        _engine_data: Option<&EngineData>,
+        engine_data: Option<&EngineData>,
         dll_ptr: &DLLPointer,
         engine_token: EngineToken,
     ) {
-        init_hooks(dll_ptr.which_dll(), engine_token)
+        if self.inert {
+            return;
+        }
+
+        if let Some(engine) = engine_data {
+            register_commands(engine, engine_token);
+            register_convars(engine_token);
+        }
+
+        if !self.is_shutting_down() {
+            init_hooks(dll_ptr.which_dll(), engine_token)
+        }
+    }
+
+    fn on_sqvm_created(&self, sqvm_handle: &CSquirrelVMHandle, _engine_token: EngineToken) {
+        let context = sqvm_handle.get_context();
+        let generation = self.debug_info.get(context).created();
+        self.events.push(context, StackTrace::VmCreated(generation));
+    }
+
+    fn on_sqvm_destroyed(&self, sqvm_handle: &CSquirrelVMHandle, _engine_token: EngineToken) {
+        let context = sqvm_handle.get_context();
+        let generation = self.debug_info.get(context).destroyed();
+        self.events
+            .push(context, StackTrace::VmDestroyed(generation));
+    }
+
+    fn runframe(&self, engine_token: EngineToken) {
+        if self.inert || self.is_shutting_down() {
+            return;
+        }
+
+        if self.settings.hooks_dirty.swap(false, Ordering::Relaxed) {
+            apply_hook_settings(engine_token);
+        }
+
+        // the timeout can change at any point so the server's pause timeout follows it
+        let network_timeout_ms = engine_network_timeout_ms(engine_token).unwrap_or_default();
+        if self
+            .settings
+            .engine_network_timeout_ms
+            .swap(network_timeout_ms, Ordering::Relaxed)
+            != network_timeout_ms
+        {
+            self.apply_pause_timeouts();
+        }
     }
 }
 
 entry!(DebugPlugin);
 
-/// .
-///
-/// # Panics
-///
-/// Panics if .
+impl DebugPlugin {
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// whether a hook has anything to do at all, checked first so an idle hook is only a few atomic loads
+    ///
+    /// nothing is recorded or armed otherwise, an open window or a sink only sees what logging records
+    pub fn is_active(&self) -> bool {
+        self.settings.logging.load(Ordering::Relaxed)
+            || self.settings.break_on_error.load(Ordering::Relaxed)
+            || self.settings.watchdog_threshold_ms.load(Ordering::Relaxed) != 0
+            || ALL_CONTEXTS
+                .into_iter()
+                .any(|context| self.debug_info.get(context).is_armed())
+    }
+
+    /// sets how long each vm may stay paused from the config and the engine's network timeout
+    pub fn apply_pause_timeouts(&self) {
+        let pause_timeout = self.settings.pause_timeout.lock();
+        let engine_timeout_ms = match self
+            .settings
+            .engine_network_timeout_ms
+            .load(Ordering::Relaxed)
+        {
+            0 => None,
+            ms => Some(ms),
+        };
+
+        for context in ALL_CONTEXTS {
+            self.debug_info.get(context).max_pause_ms.store(
+                pause_timeout.max_pause_ms(context, engine_timeout_ms),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    pub fn pause_all(&self, reason: PauseReason) {
+        for context in ALL_CONTEXTS {
+            self.debug_info.get(context).pause(reason.clone());
+        }
+    }
+
+    /// lets every vm run freely, each one gets to leave its pause before the next is released
+    pub fn unpause_all(&self) {
+        for context in ALL_CONTEXTS {
+            let state = self.debug_info.get(context);
+            let since = state.release_count();
+            state.unpause();
+            self.wait_until_released(context, since);
+        }
+    }
+
+    /// steps every paused vm to its next hook in the same order as `unpause_all`
+    pub fn step_all(&self) {
+        for context in ALL_CONTEXTS {
+            let state = self.debug_info.get(context);
+            let since = state.release_count();
+            state.resume();
+            self.wait_until_released(context, since);
+        }
+    }
+
+    fn wait_until_released(&self, context: ScriptContext, since: usize) {
+        if !self
+            .debug_info
+            .get(context)
+            .wait_until_released(since, RELEASE_TIMEOUT)
+        {
+            self.diagnostics.report(format!(
+                "the {context:?} vm didn't leave its pause in time, released the next one anyway"
+            ));
+        }
+    }
+
+    /// removes every trampoline, releases every vm and stops every thread until the game is restarted
+    ///
+    /// the dll still can't be unloaded since the engine keeps its concommands, convars and squirrel functions
+    pub fn shutdown(&self, engine_token: EngineToken) {
+        if self.shutting_down.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        teardown_hooks(engine_token);
+        self.unpause_all();
+        uninstall_exception_handler();
+
+        if self.join_threads() {
+            log::info!("dbgquirrel shut down until the game is restarted");
+        } else {
+            log::error!("dbgquirrel couldn't stop all of its threads");
+        }
+    }
+
+    /// every thread polls `shutting_down`, returns whether all of them stopped in time
+    fn join_threads(&self) -> bool {
+        let deadline = Instant::now() + THREAD_JOIN_TIMEOUT;
+        let mut stopped = true;
+
+        for thread in std::mem::take(&mut *self.threads.lock()) {
+            while !thread.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            if !thread.is_finished() {
+                stopped = false;
+            } else if thread.join().is_err() {
+                log::error!("a dbgquirrel thread panicked before shutting down");
+            }
+        }
+
+        stopped
+    }
+}
+
+/// resolves which vm a sqvm belongs to, `None` if any pointer on the way is null or the context is garbage
 ///
 /// # Safety
 ///
-/// .
-pub unsafe fn sqvm_to_context(sqvm: *mut HSquirrelVM) -> ScriptContext {
-    *(&sqvm
-        .as_ref()
-        .unwrap()
+/// `sqvm` has to be null or point to a live `HSquirrelVM`
+pub unsafe fn sqvm_to_context(sqvm: *mut HSquirrelVM) -> Option<ScriptContext> {
+    let context = sqvm
+        .as_ref()?
         .sharedState
-        .as_ref()
-        .unwrap()
+        .as_ref()?
         .cSquirrelVM
-        .as_ref()
-        .unwrap()
-        .vmContext as *const i32)
-        .cast::<ScriptContext>()
-        .as_ref()
-        .unwrap()
+        .as_ref()?
+        .vmContext as i32;
+
+    match context {
+        0 => Some(ScriptContext::SERVER),
+        1 => Some(ScriptContext::CLIENT),
+        2 => Some(ScriptContext::UI),
+        _ => None,
+    }
+}
+
+pub fn context_from_str(name: &str) -> Option<ScriptContext> {
+    match name.to_ascii_uppercase().as_str() {
+        "SERVER" | "SV" => Some(ScriptContext::SERVER),
+        "CLIENT" | "CL" => Some(ScriptContext::CLIENT),
+        "UI" => Some(ScriptContext::UI),
+        _ => None,
+    }
 }
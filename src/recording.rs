@@ -0,0 +1,120 @@
+use parking_lot::Mutex;
+use rrplug::prelude::*;
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{convars::DEFAULT_LOG_LIMIT, stacktrace::StackTrace, VmSpecific, ALL_CONTEXTS};
+
+/// the formatted trace of every vm shared between the window, headless mode and the console
+pub struct Recording {
+    logs: Mutex<VmSpecific<VecDeque<String>>>,
+    limit: AtomicUsize,
+    file_sink: Mutex<Option<File>>,
+    console_sink: AtomicBool,
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Self {
+            logs: Mutex::new(VmSpecific::new()),
+            limit: AtomicUsize::new(DEFAULT_LOG_LIMIT),
+            file_sink: Mutex::new(None),
+            console_sink: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Recording {
+    pub fn record(&self, context: ScriptContext, trace: &StackTrace) {
+        let line = trace.to_string();
+
+        if let Some(file) = self.file_sink.lock().as_mut() {
+            _ = writeln!(file, "[{context:?}] {line}");
+        }
+        if self.console_sink.load(Ordering::Relaxed) {
+            log::info!("[{context:?}] {line}");
+        }
+
+        let limit = self.limit();
+        let mut logs = self.logs.lock();
+        let log_buffer = logs.get_mut(context);
+        while log_buffer.len() >= limit {
+            log_buffer.pop_front();
+        }
+        log_buffer.push_back(line);
+    }
+
+    /// for things that should end up in the trace file but aren't part of any vm's trace
+    pub fn write_to_sink(&self, text: &str) {
+        if let Some(file) = self.file_sink.lock().as_mut() {
+            _ = writeln!(file, "{text}");
+        }
+    }
+
+    pub fn set_sinks(&self, file: Option<&Path>, console: bool) {
+        *self.file_sink.lock() = file.and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|err| {
+                    log::error!("failed to open the trace sink {}: {err}", path.display())
+                })
+                .ok()
+        });
+        self.console_sink.store(console, Ordering::Relaxed);
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// how many events each vm keeps, the window sticks to it as well
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed).max(1)
+    }
+
+    pub fn last(&self, context: ScriptContext, count: usize) -> Vec<String> {
+        let logs = self.logs.lock();
+        let log_buffer = logs.get(context);
+
+        log_buffer
+            .iter()
+            .skip(log_buffer.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+
+    /// `last` for callers that can't block like the crash handler
+    pub fn try_last(&self, context: ScriptContext, count: usize) -> Option<Vec<String>> {
+        let logs = self.logs.try_lock()?;
+        let log_buffer = logs.get(context);
+
+        Some(
+            log_buffer
+                .iter()
+                .skip(log_buffer.len().saturating_sub(count))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn dump(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        let logs = self.logs.lock();
+
+        for context in ALL_CONTEXTS {
+            writeln!(file, "==== {context:?} ====")?;
+            for line in logs.get(context) {
+                writeln!(file, "{line}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
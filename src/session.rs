@@ -0,0 +1,211 @@
+use glob::Pattern;
+use rrplug::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use crate::{
+    config::{
+        BreakpointsConfig, ConfigError, DataBreakpointsConfig, Filters, ScriptBreakpoints,
+        ScriptBreakpointsConfig,
+    },
+    context_from_str,
+    debug::{ScriptBreakKind, ScriptBreakpoint},
+    DebugPlugin, ALL_CONTEXTS,
+};
+
+const DEFAULT_SESSION_PATH: &str = "R2Northstar/plugins/dbgquirrel_session.toml";
+
+/// the parts of a debugging setup that only live in the ui
+#[derive(Default)]
+pub struct SessionState {
+    pub context: Option<ScriptContext>,
+    pub watches: Vec<String>,
+    pub filter_presets: BTreeMap<String, Filters>,
+    /// set when the session file has to be written again
+    pub dirty: bool,
+}
+
+/// what ends up in the session file, shareable with import/export
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Session {
+    pub context: Option<String>,
+    pub breakpoints: BreakpointsConfig,
+    pub script_breakpoints: ScriptBreakpointsConfig,
+    /// the predicates as they are written in the config
+    pub data_breakpoints: DataBreakpointsConfig,
+    pub watches: Vec<String>,
+    /// the filters that were active, the presets are only the saved ones
+    pub filters: Filters,
+    pub filter_presets: BTreeMap<String, Filters>,
+}
+
+pub fn session_path() -> PathBuf {
+    PathBuf::from(DEFAULT_SESSION_PATH)
+}
+
+impl Session {
+    pub fn capture(plugin: &DebugPlugin) -> Self {
+        let filters = plugin.settings.filters.lock().clone();
+        let state = plugin.session.lock();
+        // the configured ones come back from the config, saving them would keep them after they were removed there
+        let breakpoints = |context| plugin.debug_info.get(context).user_breakpoints();
+        let script_breakpoints = |context| {
+            let mut script_breakpoints = ScriptBreakpoints::default();
+            for (pattern, kind) in plugin.debug_info.get(context).user_script_breakpoints() {
+                match kind {
+                    ScriptBreakKind::Load => script_breakpoints.load.push(pattern),
+                    ScriptBreakKind::FirstCall => script_breakpoints.first_call.push(pattern),
+                }
+            }
+            script_breakpoints
+        };
+        let data_breakpoints = |context| {
+            plugin
+                .debug_info
+                .get(context)
+                .user_data_breakpoints()
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+
+        Self {
+            context: state.context.map(|context| format!("{context:?}")),
+            breakpoints: BreakpointsConfig {
+                server: breakpoints(ScriptContext::SERVER),
+                client: breakpoints(ScriptContext::CLIENT),
+                ui: breakpoints(ScriptContext::UI),
+            },
+            script_breakpoints: ScriptBreakpointsConfig {
+                server: script_breakpoints(ScriptContext::SERVER),
+                client: script_breakpoints(ScriptContext::CLIENT),
+                ui: script_breakpoints(ScriptContext::UI),
+            },
+            data_breakpoints: DataBreakpointsConfig {
+                server: data_breakpoints(ScriptContext::SERVER),
+                client: data_breakpoints(ScriptContext::CLIENT),
+                ui: data_breakpoints(ScriptContext::UI),
+            },
+            watches: state.watches.clone(),
+            filters,
+            filter_presets: state.filter_presets.clone(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+
+        toml::from_str(&source).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let source = toml::to_string_pretty(self)
+            .map_err(|err| ConfigError::Invalid(path.to_path_buf(), err.to_string()))?;
+
+        std::fs::write(path, source).map_err(|err| ConfigError::Io(path.to_path_buf(), err))
+    }
+
+    /// replaces the function, script and data breakpoints set from the ui or the console, the filters and the ui state
+    ///
+    /// configured and temporary breakpoints stay
+    pub fn apply(self, plugin: &DebugPlugin) {
+        *plugin.settings.filters.lock() = self.filters;
+
+        for context in ALL_CONTEXTS {
+            let breakpoints = match context {
+                ScriptContext::SERVER => &self.breakpoints.server,
+                ScriptContext::CLIENT => &self.breakpoints.client,
+                ScriptContext::UI => &self.breakpoints.ui,
+            };
+
+            let script_breakpoints = self.script_breakpoints.get(context);
+            let scripts = script_breakpoints
+                .load
+                .iter()
+                .map(|pattern| (pattern, ScriptBreakKind::Load))
+                .chain(
+                    script_breakpoints
+                        .first_call
+                        .iter()
+                        .map(|pattern| (pattern, ScriptBreakKind::FirstCall)),
+                )
+                .filter_map(|(pattern, kind)| match Pattern::new(pattern) {
+                    Ok(pattern) => Some(ScriptBreakpoint { pattern, kind }),
+                    Err(err) => {
+                        log::error!(
+                            "skipped the script breakpoint {pattern:?} of the session: {err}"
+                        );
+                        None
+                    }
+                })
+                .collect();
+            let predicates = self
+                .data_breakpoints
+                .get(context)
+                .iter()
+                .filter_map(|predicate| match predicate.parse() {
+                    Ok(predicate) => Some(predicate),
+                    Err(err) => {
+                        log::error!(
+                            "skipped the data breakpoint {predicate:?} of the session: {err}"
+                        );
+                        None
+                    }
+                })
+                .collect();
+
+            let state = plugin.debug_info.get(context);
+            state.replace_user_breakpoints(breakpoints);
+            state.replace_user_script_breakpoints(scripts);
+            state.replace_user_data_breakpoints(predicates);
+        }
+
+        let mut state = plugin.session.lock();
+        state.context = self.context.as_deref().and_then(context_from_str);
+        state.watches = self.watches;
+        state.filter_presets = self.filter_presets;
+        state.dirty = true;
+    }
+}
+
+/// restores the last session, a missing file just means there is nothing to restore
+pub fn restore_session(plugin: &DebugPlugin) {
+    let path = session_path();
+    if !path.exists() {
+        return;
+    }
+
+    match Session::load(&path) {
+        Ok(session) => session.apply(plugin),
+        Err(err) => log::error!("failed to restore the session: {err}"),
+    }
+}
+
+/// writes the session file if anything changed since the last save
+///
+/// polled by the window or headless thread so the engine thread never waits on the disk
+pub fn save_session_if_changed(plugin: &DebugPlugin) {
+    let breakpoints_changed = ALL_CONTEXTS.into_iter().fold(false, |changed, context| {
+        plugin
+            .debug_info
+            .get(context)
+            .breakpoints_changed
+            .swap(false, Ordering::Relaxed)
+            || changed
+    });
+    let session_changed = std::mem::take(&mut plugin.session.lock().dirty);
+
+    if !breakpoints_changed && !session_changed {
+        return;
+    }
+
+    if let Err(err) = Session::capture(plugin).save(&session_path()) {
+        log::error!("failed to save the session: {err}");
+    }
+}
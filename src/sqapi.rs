@@ -0,0 +1,63 @@
+use rrplug::{bindings::squirreldatatypes::SQObject, prelude::*};
+use std::sync::atomic::Ordering;
+
+use crate::{
+    debug::PauseReason,
+    exports::PLUGIN,
+    hooks::{push_log, try_debug},
+    sqvm_to_context,
+    stacktrace::{StackTrace, TraceValue},
+};
+
+pub fn register_sq_api() {
+    register_sq_functions(debug_break);
+    register_sq_functions(debug_log);
+    register_sq_functions(debug_watch);
+    register_sq_functions(debug_is_attached);
+}
+
+/// pauses the calling vm like a breakpoint would
+#[rrplug::sqfunction(VM = "SERVER | CLIENT | UI", ExportName = "DebugBreak")]
+fn debug_break() -> Result<(), String> {
+    push_log(sqvm, StackTrace::ScriptBreak);
+    match unsafe { sqvm_to_context(sqvm) } {
+        Some(context) => PLUGIN
+            .wait()
+            .debug_info
+            .get(context)
+            .pause(PauseReason::Script),
+        None => PLUGIN
+            .wait()
+            .diagnostics
+            .report("DebugBreak was called from an unknown vm"),
+    }
+    try_debug(sqvm, "DebugBreak");
+
+    Ok(())
+}
+
+#[rrplug::sqfunction(VM = "SERVER | CLIENT | UI", ExportName = "DebugLog")]
+fn debug_log(message: String) -> Result<(), String> {
+    push_log(sqvm, StackTrace::ScriptLog(message));
+
+    Ok(())
+}
+
+#[rrplug::sqfunction(VM = "SERVER | CLIENT | UI", ExportName = "DebugWatch")]
+fn debug_watch(name: String, value: SQObject) -> Result<(), String> {
+    push_log(
+        sqvm,
+        StackTrace::Watch {
+            name,
+            value: unsafe { TraceValue::from_object(sqvm, &value) },
+        },
+    );
+
+    Ok(())
+}
+
+/// lets scripts skip expensive debug only work when nobody is looking
+#[rrplug::sqfunction(VM = "SERVER | CLIENT | UI", ExportName = "DebugIsAttached")]
+fn debug_is_attached() -> Result<bool, String> {
+    Ok(PLUGIN.wait().attached.load(Ordering::Relaxed))
+}
@@ -1,73 +1,506 @@
-use std::{ffi::CStr, fmt::Display};
+use std::{ffi::CStr, fmt::Display, time::Duration};
 
-use rrplug::{bindings::squirreldatatypes::SQClosure, high::squirrel::SQHandle, prelude::*};
+use rrplug::{
+    bindings::squirreldatatypes::{SQClosure, SQNativeClosure, SQObject, SQObjectType, SQString},
+    high::squirrel::SQHandle,
+    prelude::*,
+};
 
+use crate::hooks::entity_index;
+
+/// how many bytes of a function name an event keeps
+pub const NAME_CAPACITY: usize = 48;
+/// how many bytes of a string value an event keeps
+pub const VALUE_CAPACITY: usize = 22;
+/// arguments past this are only counted
+pub const MAX_ARGS: usize = 6;
+
+/// events are built on the vm threads so they only hold copies of raw values, the consumer formats them
 pub enum StackTrace {
-    Call(String),
-    Pushed(String),
-    Aquired(String),
+    Call {
+        name: FunctionName,
+        args: CallArgs,
+        depth: usize,
+    },
+    Return {
+        depth: usize,
+        elapsed: Duration,
+    },
+    Pushed(TraceValue),
+    Aquired(TraceValue),
     Misc(String),
     DebugBegin(i32),
+    ScriptLog(String),
+    Watch {
+        name: String,
+        value: TraceValue,
+    },
+    ScriptBreak,
+    VmCreated(usize),
+    VmDestroyed(usize),
 }
 
 impl StackTrace {
-    pub fn value_pushed<T>(value: T) -> Self
-    where
-        T: ToString,
-    {
-        Self::Pushed(value.to_string())
+    pub fn value_pushed(value: impl Into<TraceValue>) -> Self {
+        Self::Pushed(value.into())
+    }
+
+    pub fn value_gotten(value: impl Into<TraceValue>) -> Self {
+        Self::Aquired(value.into())
+    }
+
+    /// the closure of a `sq_call` sits right below its `args` on the top of the stack
+    ///
+    /// the first arg is always `this`
+    pub fn func_called(sqvm_ptr: *mut HSquirrelVM, args: SQInteger, depth: usize) -> Self {
+        let Some(sqvm) = (unsafe { sqvm_ptr.as_ref() }) else {
+            return Self::Call {
+                name: FunctionName::Unknown,
+                args: CallArgs::default(),
+                depth,
+            };
+        };
+
+        let base = (sqvm._top - args).max(sqvm._stackbase);
+        let name = FunctionName::called(sqvm, args);
+        let mut call_args = CallArgs::default();
+        for object in (base..sqvm._top).filter_map(|pos| stack_object(sqvm, pos)) {
+            call_args.push(|| unsafe { TraceValue::from_object(sqvm_ptr, object) });
+        }
+
+        Self::Call {
+            name,
+            args: call_args,
+            depth,
+        }
+    }
+}
+
+/// a string copied into the event itself, cut off at a char boundary when it doesn't fit into `N` bytes
+///
+/// `N` has to fit into a u8
+#[derive(Clone, Copy)]
+pub struct InlineStr<const N: usize> {
+    bytes: [u8; N],
+    len: u8,
+    truncated: bool,
+}
+
+impl<const N: usize> InlineStr<N> {
+    pub fn new(string: &str) -> Self {
+        Self::from_bytes(string.as_bytes())
+    }
+
+    /// invalid utf-8 ends the string just like running out of space does
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let fitting = &bytes[..bytes.len().min(N)];
+        let len = match std::str::from_utf8(fitting) {
+            Ok(valid) => valid.len(),
+            Err(err) => err.valid_up_to(),
+        };
+
+        let mut inline = Self {
+            bytes: [0; N],
+            len: len as u8,
+            truncated: len < bytes.len(),
+        };
+        inline.bytes[..len].copy_from_slice(&bytes[..len]);
+        inline
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<const N: usize> Display for InlineStr<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())?;
+        if self.truncated {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
+/// a squirrel value as an event holds it
+#[derive(Clone, Copy)]
+pub enum TraceValue {
+    Null,
+    Bool(bool),
+    Integer(SQInteger),
+    Float(f32),
+    String(InlineStr<VALUE_CAPACITY>),
+    Table,
+    /// how many slots are used if the array could be read
+    Array(Option<u32>),
+    Function {
+        name: InlineStr<VALUE_CAPACITY>,
+        native: bool,
+    },
+    /// the entity index if it could be resolved
+    Entity(Option<u32>),
+    Instance,
+    Struct,
+    Vector([f32; 3]),
+    Other(SQObjectType),
+}
+
+impl TraceValue {
+    /// a null pointer shows up as `null`
+    pub fn from_ptr(ptr: *const SQChar) -> Self {
+        if ptr.is_null() {
+            return Self::Null;
+        }
+        Self::String(InlineStr::from_bytes(
+            unsafe { CStr::from_ptr(ptr) }.to_bytes(),
+        ))
+    }
+
+    /// `sqvm` is only used to look up entities, it may be null
+    pub(crate) unsafe fn from_object(sqvm: *mut HSquirrelVM, object: &SQObject) -> Self {
+        match object._Type {
+            SQObjectType::OT_NULL => Self::Null,
+            SQObjectType::OT_BOOL => Self::Bool(object._VAL.asInteger != 0),
+            SQObjectType::OT_INTEGER => Self::Integer(object._VAL.asInteger),
+            SQObjectType::OT_FLOAT => Self::Float(object._VAL.asFloat),
+            SQObjectType::OT_STRING | SQObjectType::OT_ASSET => Self::String(
+                object
+                    ._VAL
+                    .asString
+                    .as_ref()
+                    .map(|string| InlineStr::from_bytes(sqstring_bytes(string)))
+                    .unwrap_or_else(|| InlineStr::new("")),
+            ),
+            SQObjectType::OT_TABLE => Self::Table,
+            SQObjectType::OT_ARRAY => Self::Array(
+                object
+                    ._VAL
+                    .asArray
+                    .as_ref()
+                    .map(|array| array._usedSlots as u32),
+            ),
+            SQObjectType::OT_CLOSURE | SQObjectType::OT_NATIVECLOSURE => {
+                match closure_name(*object) {
+                    Some((name, native)) => Self::Function {
+                        name: InlineStr::from_bytes(name),
+                        native,
+                    },
+                    None => Self::Function {
+                        name: InlineStr::new("<unknown>"),
+                        native: false,
+                    },
+                }
+            }
+            SQObjectType::OT_ENTITY => Self::Entity(entity_index(sqvm, object)),
+            SQObjectType::OT_INSTANCE => Self::Instance,
+            SQObjectType::OT_STRUCT => Self::Struct,
+            SQObjectType::OT_VECTOR => Self::Vector(vector_components(object)),
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl From<SQInteger> for TraceValue {
+    fn from(value: SQInteger) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<f32> for TraceValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<bool> for TraceValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl Display for TraceValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceValue::Null => f.write_str("null"),
+            TraceValue::Bool(value) => value.fmt(f),
+            TraceValue::Integer(value) => value.fmt(f),
+            TraceValue::Float(value) => value.fmt(f),
+            TraceValue::String(value) => f.write_fmt(format_args!("\"{value}\"")),
+            TraceValue::Table => f.write_str("table{...}"),
+            TraceValue::Array(Some(len)) => f.write_fmt(format_args!("array[{len}]")),
+            TraceValue::Array(None) => f.write_str("array[]"),
+            TraceValue::Function { name, native } => {
+                f.write_fmt(format_args!("function {name}"))?;
+                if *native {
+                    f.write_str(" [native]")?;
+                }
+                Ok(())
+            }
+            TraceValue::Entity(Some(index)) => f.write_fmt(format_args!("entity#{index}")),
+            TraceValue::Entity(None) => f.write_str("entity"),
+            TraceValue::Instance => f.write_str("instance"),
+            TraceValue::Struct => f.write_str("struct{...}"),
+            TraceValue::Vector([x, y, z]) => f.write_fmt(format_args!("vector({x}, {y}, {z})")),
+            TraceValue::Other(kind) => f.write_fmt(format_args!("{kind:?}")),
+        }
+    }
+}
+
+/// the arguments of a call, the ones past `MAX_ARGS` are only counted
+#[derive(Clone, Copy)]
+pub struct CallArgs {
+    values: [TraceValue; MAX_ARGS],
+    kept: u8,
+    count: usize,
+}
+
+impl Default for CallArgs {
+    fn default() -> Self {
+        Self {
+            values: [TraceValue::Null; MAX_ARGS],
+            kept: 0,
+            count: 0,
+        }
+    }
+}
+
+impl CallArgs {
+    /// `value` is only read if there is still room for it
+    pub fn push(&mut self, value: impl FnOnce() -> TraceValue) {
+        if (self.kept as usize) < MAX_ARGS {
+            self.values[self.kept as usize] = value();
+            self.kept += 1;
+        }
+        self.count += 1;
+    }
+
+    pub fn kept(&self) -> &[TraceValue] {
+        &self.values[..self.kept as usize]
     }
 
-    pub fn value_gotten<T>(value: T) -> Self
-    where
-        T: ToString,
-    {
-        Self::Pushed(value.to_string())
+    /// every argument the call got, also the ones that weren't kept
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl Display for CallArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, value) in self.kept().iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            value.fmt(f)?;
+        }
+
+        let dropped = self.count - self.kept().len();
+        if dropped != 0 {
+            f.write_fmt(format_args!(", {dropped} more"))?;
+        }
+        Ok(())
+    }
+}
+
+/// a name cut off at `NAME_CAPACITY` can't be matched against breakpoints or filters
+#[derive(Clone, Copy)]
+pub enum FunctionName {
+    Script(InlineStr<NAME_CAPACITY>),
+    Native(InlineStr<NAME_CAPACITY>),
+    Unknown,
+}
+
+impl FunctionName {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FunctionName::Script(name) | FunctionName::Native(name) if !name.is_truncated() => {
+                Some(name.as_str())
+            }
+            _ => None,
+        }
     }
 
-    pub fn func_called(sqvm: *mut HSquirrelVM) -> Self {
-        // SQFUNCTIONS.from_sqvm(sqvm).
+    /// only the name part of `StackTrace::func_called`
+    pub fn called(sqvm: &HSquirrelVM, args: SQInteger) -> Self {
+        stack_object(sqvm, sqvm._top - args - 1)
+            .map(|object| unsafe { FunctionName::from_object(*object) })
+            .unwrap_or(FunctionName::Unknown)
+    }
 
+    /// the script a called closure was compiled from, natives don't have one
+    pub fn called_file(sqvm: &HSquirrelVM, args: SQInteger) -> Option<String> {
         unsafe {
-            let sqvm = sqvm.as_mut().unwrap();
-            // let info = sqvm._callstack.as_mut().unwrap();
-            let function_name = (0..20)
-                .filter_map(|i| sqvm._stack.add(i).as_ref())
-                .find_map(|object| SQHandle::<SQClosure>::new(*object).ok())
-                .map(|closure| {
-                    CStr::from_ptr(
-                        closure
-                            .take()
-                            ._VAL
-                            .asClosure
-                            .as_mut()
-                            .unwrap()
-                            ._function
-                            ._VAL
-                            .asString
-                            .as_ref()
-                            .unwrap()
-                            ._val
-                            .as_ptr(),
-                    )
-                    .to_string_lossy()
-                    .to_string()
-                })
-                .unwrap_or_else(|| "UNK".to_string());
-
-            Self::Call(function_name)
+            let object = stack_object(sqvm, sqvm._top - args - 1)?;
+            let closure = SQHandle::<SQClosure>::new(*object).ok()?.take();
+            let proto = closure
+                ._VAL
+                .asClosure
+                .as_ref()?
+                ._function
+                ._VAL
+                .asFuncProto
+                .as_ref()?;
+            proto.fileName.as_ref().map(|file| sqstring_to_string(file))
+        }
+    }
+
+    unsafe fn from_object(object: SQObject) -> Self {
+        match closure_name(object) {
+            Some((name, false)) => Self::Script(InlineStr::from_bytes(name)),
+            Some((name, true)) => Self::Native(InlineStr::from_bytes(name)),
+            None => Self::Unknown,
         }
     }
 }
 
+/// the name of a closure and whether it's a native one, borrowed straight from the vm
+unsafe fn closure_name<'a>(object: SQObject) -> Option<(&'a [u8], bool)> {
+    if let Ok(closure) = SQHandle::<SQClosure>::new(object) {
+        return closure
+            .take()
+            ._VAL
+            .asClosure
+            .as_ref()
+            .and_then(|closure| closure._function._VAL.asString.as_ref())
+            .map(|name| (sqstring_bytes(name), false));
+    }
+
+    if let Ok(closure) = SQHandle::<SQNativeClosure>::new(object) {
+        return closure
+            .take()
+            ._VAL
+            .asNativeClosure
+            .as_ref()
+            .and_then(|closure| closure._name._VAL.asString.as_ref())
+            .map(|name| (sqstring_bytes(name), true));
+    }
+
+    None
+}
+
+impl Display for FunctionName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FunctionName::Script(name) => name.fmt(f),
+            FunctionName::Native(name) => f.write_fmt(format_args!("{name} [native]")),
+            FunctionName::Unknown => f.write_str("<unknown>"),
+        }
+    }
+}
+
+/// only hands out slots of the current frame, a bogus `args` count can't reach outside of it
+fn stack_object(sqvm: &HSquirrelVM, pos: SQInteger) -> Option<&SQObject> {
+    if pos < sqvm._stackbase || pos >= sqvm._top {
+        return None;
+    }
+    unsafe { sqvm._stack.offset(pos as isize).as_ref() }
+}
+
+/// respawn's squirrel keeps vectors inline, the 3 floats fill the object right after its type
+unsafe fn vector_components(object: &SQObject) -> [f32; 3] {
+    let floats = (object as *const SQObject).cast::<f32>();
+    [*floats.add(1), *floats.add(2), *floats.add(3)]
+}
+
+unsafe fn sqstring_to_string(string: &SQString) -> String {
+    String::from_utf8_lossy(sqstring_bytes(string)).to_string()
+}
+
+unsafe fn sqstring_bytes(string: &SQString) -> &[u8] {
+    CStr::from_ptr(string._val.as_ptr()).to_bytes()
+}
+
 impl Display for StackTrace {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            StackTrace::Call(func) => f.write_fmt(format_args!("Called {func}")),
+            StackTrace::Call { name, args, .. } => {
+                f.write_fmt(format_args!("Called {name}({args})"))
+            }
+            StackTrace::Return { depth, elapsed } => f.write_fmt(format_args!(
+                "Returned from depth {depth} after {elapsed:?}"
+            )),
             StackTrace::Pushed(pushed) => f.write_fmt(format_args!("Pushed {pushed}")),
             StackTrace::Aquired(got) => f.write_fmt(format_args!("Feteched {got}")),
             StackTrace::Misc(misc) => f.write_fmt(format_args!("{misc}")),
             StackTrace::DebugBegin(i) => f.write_fmt(format_args!("debug {i}")),
+            StackTrace::ScriptLog(message) => f.write_fmt(format_args!("DebugLog: {message}")),
+            StackTrace::Watch { name, value } => {
+                f.write_fmt(format_args!("DebugWatch: {name} = {value}"))
+            }
+            StackTrace::ScriptBreak => f.write_str("DebugBreak"),
+            StackTrace::VmCreated(generation) => f.write_fmt(format_args!(
+                "==== vm created (generation {generation}) ===="
+            )),
+            StackTrace::VmDestroyed(generation) => f.write_fmt(format_args!(
+                "==== vm destroyed (generation {generation}) ===="
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_str_keeps_short_strings() {
+        let name = InlineStr::<8>::new("OnDamage");
+        assert_eq!(name.as_str(), "OnDamage");
+        assert!(!name.is_truncated());
+        assert_eq!(name.to_string(), "OnDamage");
+    }
+
+    #[test]
+    fn inline_str_cuts_at_a_char_boundary() {
+        let name = InlineStr::<4>::new("abcdef");
+        assert_eq!(name.as_str(), "abcd");
+        assert!(name.is_truncated());
+        assert_eq!(name.to_string(), "abcd...");
+
+        // the third byte is in the middle of the "é"
+        let name = InlineStr::<3>::new("aéb");
+        assert_eq!(name.as_str(), "aé");
+        let name = InlineStr::<2>::new("aéb");
+        assert_eq!(name.as_str(), "a");
+        assert!(name.is_truncated());
+    }
+
+    #[test]
+    fn inline_str_stops_at_invalid_utf8() {
+        let name = InlineStr::<8>::from_bytes(b"ab\xffcd");
+        assert_eq!(name.as_str(), "ab");
+        assert!(name.is_truncated());
+    }
+
+    #[test]
+    fn truncated_names_dont_match() {
+        let name = "a".repeat(NAME_CAPACITY + 1);
+        assert_eq!(FunctionName::Script(InlineStr::new(&name)).as_str(), None);
+        assert_eq!(
+            FunctionName::Native(InlineStr::new("GetPlayerArray")).as_str(),
+            Some("GetPlayerArray")
+        );
+    }
+
+    #[test]
+    fn call_args_count_what_they_drop() {
+        let mut args = CallArgs::default();
+        assert_eq!(args.to_string(), "");
+
+        args.push(|| TraceValue::Null);
+        args.push(|| TraceValue::String(InlineStr::new("mp_titan")));
+        args.push(|| TraceValue::Vector([1.0, 2.5, -3.0]));
+        assert_eq!(args.to_string(), "null, \"mp_titan\", vector(1, 2.5, -3)");
+
+        for i in 0..MAX_ARGS {
+            args.push(|| TraceValue::Integer(i as SQInteger));
         }
+        assert_eq!(args.kept().len(), MAX_ARGS);
+        assert_eq!(args.count(), MAX_ARGS + 3);
+        assert!(args.to_string().ends_with(", 3 more"));
     }
 }
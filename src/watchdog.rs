@@ -0,0 +1,100 @@
+use rrplug::prelude::*;
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use crate::{debug::PauseReason, exports::PLUGIN, stacktrace::StackTrace, ALL_CONTEXTS};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// what the watchdog found in one vm
+struct Report {
+    function: String,
+    elapsed: Duration,
+    callstack: Vec<String>,
+}
+
+pub fn watchdog_enabled() -> bool {
+    PLUGIN
+        .wait()
+        .settings
+        .watchdog_threshold_ms
+        .load(Ordering::Relaxed)
+        != 0
+}
+
+/// looks for `sq_call`s that never return, like a script stuck in an endless loop
+pub fn run() {
+    let plugin = PLUGIN.wait();
+    while !plugin.is_shutting_down() {
+        std::thread::sleep(POLL_INTERVAL);
+
+        let threshold = plugin
+            .settings
+            .watchdog_threshold_ms
+            .load(Ordering::Relaxed);
+        if threshold == 0 {
+            continue;
+        }
+        let threshold = Duration::from_millis(threshold as u64);
+
+        let now = Instant::now();
+        for context in ALL_CONTEXTS {
+            if let Some(report) = check(context, threshold, now) {
+                on_report(context, report);
+            }
+        }
+    }
+}
+
+/// reports the innermost call that is over the threshold since that is the most specific one
+fn check(context: ScriptContext, threshold: Duration, now: Instant) -> Option<Report> {
+    let state = PLUGIN.wait().debug_info.get(context);
+    // a paused vm isn't running its calls, they are moved past the pause once it continues
+    if state.run_state.lock().is_paused() {
+        return None;
+    }
+
+    let mut call_stack = state.call_stack.lock();
+    let callstack = call_stack
+        .iter()
+        .map(|frame| frame.function.to_string())
+        .collect();
+
+    let frame = call_stack
+        .iter_mut()
+        .rev()
+        .find(|frame| now.duration_since(frame.entered) > threshold)?;
+    if frame.reported {
+        return None;
+    }
+    frame.reported = true;
+
+    Some(Report {
+        function: frame.function.to_string(),
+        elapsed: now.duration_since(frame.entered),
+        callstack,
+    })
+}
+
+fn on_report(context: ScriptContext, report: Report) {
+    let plugin = PLUGIN.wait();
+    let message = format!(
+        "watchdog: {} has been running for {:?}; callstack: {}",
+        report.function,
+        report.elapsed,
+        report.callstack.join(" > ")
+    );
+
+    log::warn!("[{context:?}] {message}");
+    plugin.events.push(context, StackTrace::Misc(message));
+
+    // a loop that never reaches a hook can't be stopped, the pause only lands on the next hook
+    if plugin.settings.watchdog_pause.load(Ordering::Relaxed) {
+        plugin
+            .debug_info
+            .get(context)
+            .pause(PauseReason::Watchdog(report.function));
+    }
+}